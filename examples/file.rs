@@ -1,7 +1,5 @@
-use std::collections::HashMap;
-
 use blitzkrieg::{
-    http::{BodyType, Request, Response},
+    http::{Body, BodyType, Request, Response, StatusCode},
     server::Server,
 };
 
@@ -11,19 +9,13 @@ fn main() {
 }
 
 fn handler(req: Request) -> Response {
-    let mut res = Response::new(200);
+    let mut res = Response::new(StatusCode::OK);
     let file = if let Some(BodyType::File(file)) = req.body {
         file
     } else {
         return res.body("Hello, world!");
     };
-    std::fs::write(format!("file.{}", file.extension), &file.content.clone()).unwrap();
-    res.body = Some(file.content);
-    let mut headers = HashMap::new();
-    headers.insert(
-        "Content-Type".into(),
-        req.headers.get("Content-Type").unwrap().into(),
-    );
-    res.headers = Some(headers);
-    res
+    std::fs::write(format!("file.{}", file.extension), &file.content).unwrap();
+    res.body = Body::Bytes(file.content);
+    res.header("Content-Type", req.headers.get("Content-Type").unwrap())
 }
@@ -1,4 +1,4 @@
-use blitzkrieg::http::{Request, Response};
+use blitzkrieg::http::{Request, Response, StatusCode};
 use blitzkrieg::server::Server;
 
 fn main() {
@@ -7,5 +7,5 @@ fn main() {
 }
 
 fn handler(_req: Request) -> Response {
-    Response::new(200).body("Hello, world!")
+    Response::new(StatusCode::OK).body("Hello, world!")
 }
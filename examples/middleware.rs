@@ -0,0 +1,27 @@
+use blitzkrieg::http::{Request, Response, StatusCode};
+use blitzkrieg::middleware::{BasicAuth, Next, RequestId, Timing};
+use blitzkrieg::server::Server;
+
+fn main() {
+    let server = Server::builder()
+        .address("127.0.0.1:3000")
+        .threads(4)
+        .handler(handler)
+        .wrap(RequestId::new())
+        .wrap(Timing)
+        .wrap(logger)
+        .wrap(BasicAuth::single("admin", "admin", "secret"))
+        .build();
+    server.start();
+}
+
+fn logger(req: Request, next: Next) -> Response {
+    let path = req.path.clone();
+    let res = next.run(req);
+    println!("{path} -> {}", res.status_code);
+    res
+}
+
+fn handler(_req: Request) -> Response {
+    Response::new(StatusCode::OK).body("Hello, admin!")
+}
@@ -1,4 +1,4 @@
-use blitzkrieg::http::{BodyType, Request, Response};
+use blitzkrieg::http::{BodyType, Formdata, Request, Response, StatusCode};
 use blitzkrieg::server::Server;
 use std::fs;
 
@@ -11,13 +11,27 @@ fn handler(req: Request) -> Response {
     match req.body.unwrap() {
         BodyType::FormdataBody(formdata_body) => {
             println!("Request content-type is multipart/form-data");
-            for field in formdata_body.fields.unwrap_or_default() {
-                println!("Name {}, value {}", field.name, field.value);
+            for part in formdata_body.parts {
+                match part {
+                    Formdata::FormdataText(field) => {
+                        println!("Name {}, value {}", field.name, field.value);
+                    }
+                    Formdata::FormdataFile(file) => {
+                        let content_type = file.content_type.unwrap_or_default();
+                        println!(
+                            "Name {}, filename {}, type {content_type}",
+                            file.name, file.file_name
+                        );
+                        // Save file to disk
+                        fs::write(file.file_name, file.content).unwrap();
+                    }
+                }
             }
-            for file in formdata_body.files.unwrap_or_default() {
-                println!("Name {}, filename {}", file.name, file.file_name);
-                // Save file to disk
-                fs::write(file.file_name, file.content).unwrap();
+        }
+        BodyType::UrlEncoded(fields) => {
+            println!("Request content-type is application/x-www-form-urlencoded");
+            for field in fields {
+                println!("Name {}, value {}", field.name, field.value);
             }
         }
         BodyType::Text(text_body) => {
@@ -28,6 +42,9 @@ fn handler(req: Request) -> Response {
             println!("Request content-type is file");
             fs::write("file", file_body.content).unwrap();
         }
+        BodyType::Multipart(_) => {
+            println!("Multipart bodies are only streamed when enabled in the server's config");
+        }
     }
-    Response::new(200).body("Hello, world!")
+    Response::new(StatusCode::OK).body("Hello, world!")
 }
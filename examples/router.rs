@@ -0,0 +1,20 @@
+use blitzkrieg::http::{Request, Response, StatusCode};
+use blitzkrieg::router::Router;
+use blitzkrieg::server::Server;
+
+fn main() {
+    let router = Router::new()
+        .get("/", |_| Response::new(StatusCode::OK).body("Hello, world!"))
+        .get("/users/:id", get_user)
+        .get("/static/*path", |req: Request| {
+            Response::new(StatusCode::OK)
+                .body(&format!("Static file {}", req.param("path").unwrap()))
+        });
+    let server = Server::new("127.0.0.1:3000", 4, router.into_handler());
+    server.start();
+}
+
+fn get_user(req: Request) -> Response {
+    let id = req.param("id").unwrap();
+    Response::new(StatusCode::OK).body(&format!("User {id}"))
+}
@@ -0,0 +1,8 @@
+use blitzkrieg::server::Server;
+use blitzkrieg::static_files::StaticFiles;
+
+fn main() {
+    let files = StaticFiles::new("/", "public").listing(true);
+    let server = Server::new("127.0.0.1:3000", 4, files.into_handler());
+    server.start();
+}
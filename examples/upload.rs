@@ -0,0 +1,59 @@
+use blitzkrieg::http::{BodyType, Request, Response, StatusCode};
+use blitzkrieg::server::{Server, ServerConfig};
+use std::path::Path;
+
+/// Largest file kept from an upload.
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+fn main() {
+    let config = ServerConfig {
+        stream_multipart: true,
+        ..ServerConfig::default()
+    };
+    let server = Server::builder()
+        .address("127.0.0.1:3000")
+        .config(config)
+        .handler(handler)
+        .build();
+    server.start();
+}
+
+fn handler(req: Request) -> Response {
+    let Some(BodyType::Multipart(mut multipart)) = req.body else {
+        return Response::new(StatusCode::BAD_REQUEST).body("Expected a multipart/form-data body");
+    };
+    loop {
+        let mut part = match multipart.next_part() {
+            Ok(Some(part)) => part,
+            Ok(None) => break,
+            Err(err) => return Response::new(StatusCode::BAD_REQUEST).body(&err.to_string()),
+        };
+        let Some(file_name) = part.file_name().map(|name| name.to_string()) else {
+            let value = part.text(1024).unwrap_or_default();
+            println!("Name {}, value {value}", part.name());
+            continue;
+        };
+        match part.save_to_temp(MAX_FILE_SIZE) {
+            Ok(file) => {
+                println!(
+                    "Name {}, filename {file_name}, {} bytes",
+                    part.name(),
+                    file.size()
+                );
+                // Keep only the file name, the client could send a path like `../../.bashrc`.
+                let file_name = file_name.rsplit('\\').next().unwrap_or_default();
+                let Some(file_name) = Path::new(file_name).file_name() else {
+                    return Response::new(StatusCode::BAD_REQUEST)
+                        .body(&format!("Invalid file name {file_name:?}"));
+                };
+                if let Err(err) = file.persist(file_name) {
+                    println!("Error in saving {file_name:?}. {err}");
+                    return Response::new(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body("Internal Server Error");
+                }
+            }
+            Err(err) => return Response::new(StatusCode::CONTENT_TOO_LARGE).body(&err.to_string()),
+        }
+    }
+    Response::new(StatusCode::OK).body("Uploaded")
+}
@@ -0,0 +1,163 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+/// HTTP Response body.
+///
+/// Bodies with a known length are sent with a `Content-Length` header,
+/// others are sent with `Transfer-Encoding: chunked` as they're produced.
+#[derive(Default)]
+pub enum Body {
+    /// No body.
+    #[default]
+    Empty,
+    /// A body held in memory.
+    Bytes(Vec<u8>),
+    /// A body read from a reader, like a file, with its length if it's known.
+    Reader {
+        reader: Box<dyn Read + Send>,
+        length: Option<u64>,
+    },
+    /// A body produced chunk by chunk.
+    Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+}
+
+impl Body {
+    /// Creates a body that's read from a reader.
+    /// Pass the length if it's known, so the body isn't chunked.
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, length: Option<u64>) -> Self {
+        Self::Reader {
+            reader: Box::new(reader),
+            length,
+        }
+    }
+
+    /// Creates a body from an iterator of chunks.
+    pub fn from_chunks<I>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::Chunks(Box::new(chunks.into_iter()))
+    }
+
+    /// Returns the length of the body, or `None` if it's only known once the body is read.
+    pub fn length(&self) -> Option<u64> {
+        match self {
+            Self::Empty => Some(0),
+            Self::Bytes(bytes) => Some(bytes.len() as u64),
+            Self::Reader { length, .. } => *length,
+            Self::Chunks(_) => None,
+        }
+    }
+
+    /// Reads the whole body into memory.
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Empty => Ok(vec![]),
+            Self::Bytes(bytes) => Ok(bytes),
+            Self::Reader { mut reader, length } => {
+                let mut bytes = vec![];
+                match length {
+                    Some(length) => reader.take(length).read_to_end(&mut bytes)?,
+                    None => reader.read_to_end(&mut bytes)?,
+                };
+                Ok(bytes)
+            }
+            Self::Chunks(chunks) => Ok(chunks.flatten().collect()),
+        }
+    }
+
+    /// Writes the body as is, for a body whose length was sent in a `Content-Length` header.
+    pub(crate) fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::Empty => Ok(()),
+            Self::Bytes(bytes) => writer.write_all(&bytes),
+            Self::Reader { mut reader, length } => {
+                let copied = match length {
+                    Some(length) => io::copy(&mut reader.take(length), writer)?,
+                    None => io::copy(&mut reader, writer)?,
+                };
+                if length.is_some_and(|length| copied < length) {
+                    // The promised Content-Length can't be honoured anymore.
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(())
+            }
+            Self::Chunks(chunks) => {
+                for chunk in chunks {
+                    writer.write_all(&chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the body with the chunked transfer coding.
+    pub(crate) fn write_chunked_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::Empty => {}
+            Self::Bytes(bytes) => write_chunk(writer, &bytes)?,
+            Self::Reader { mut reader, .. } => {
+                let mut buffer = vec![0; 8 * 1024];
+                loop {
+                    let n = match reader.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err),
+                    };
+                    write_chunk(writer, &buffer[..n])?;
+                }
+            }
+            Self::Chunks(chunks) => {
+                for chunk in chunks {
+                    write_chunk(writer, &chunk)?;
+                }
+            }
+        }
+        writer.write_all(b"0\r\n\r\n")
+    }
+}
+
+/// Writes a single chunk. Empty chunks are skipped, since they would end the body.
+fn write_chunk<W: Write>(writer: &mut W, chunk: &[u8]) -> io::Result<()> {
+    if chunk.is_empty() {
+        return Ok(());
+    }
+    write!(writer, "{:x}\r\n", chunk.len())?;
+    writer.write_all(chunk)?;
+    writer.write_all(b"\r\n")
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty"),
+            Self::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Self::Reader { length, .. } => {
+                f.debug_struct("Reader").field("length", length).finish()
+            }
+            Self::Chunks(_) => write!(f, "Chunks"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Self::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Self {
+        Self::Bytes(text.as_bytes().to_vec())
+    }
+}
@@ -0,0 +1,122 @@
+use super::{parser::read_line_limited, Error, HeaderMap, Limits};
+use std::io::{self, BufRead, Read};
+
+/// Maximum length of a chunk size line, including chunk extensions.
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// Decodes a `Transfer-Encoding: chunked` body as it's read.
+///
+/// Chunk extensions are ignored and trailers are collected once the last chunk is read.
+/// Errors are returned as [`io::Error`]s wrapping an [`Error`].
+pub(crate) struct ChunkedDecoder<R> {
+    reader: R,
+    /// Bytes left in the current chunk.
+    remaining: usize,
+    /// Whether a chunk's data was read and its CRLF is still pending.
+    in_chunk: bool,
+    done: bool,
+    decoded: usize,
+    limits: Limits,
+    trailers: HeaderMap,
+}
+
+impl<R: BufRead> ChunkedDecoder<R> {
+    pub(crate) fn new(reader: R, limits: &Limits) -> Self {
+        Self {
+            reader,
+            remaining: 0,
+            in_chunk: false,
+            done: false,
+            decoded: 0,
+            limits: limits.clone(),
+            trailers: HeaderMap::new(),
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub(crate) fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns the trailers, which are only available once the body was read to the end.
+    pub(crate) fn into_trailers(self) -> HeaderMap {
+        self.trailers
+    }
+
+    /// Reads the next chunk size line, returning the size of the chunk.
+    fn read_size(&mut self) -> Result<usize, Error> {
+        if self.in_chunk {
+            let line = read_line_limited(&mut self.reader, 2, Error::BadChunk)?;
+            if line != b"\r\n" {
+                return Err(Error::BadChunk);
+            }
+            self.in_chunk = false;
+        }
+        let line = read_line_limited(&mut self.reader, MAX_CHUNK_LINE, Error::BadChunk)?;
+        let line = line.strip_suffix(b"\r\n").ok_or(Error::BadChunk)?;
+        let line = std::str::from_utf8(line).map_err(|_| Error::BadChunk)?;
+        // Chunk extensions follow the size after a ';'.
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::BadChunk);
+        }
+        usize::from_str_radix(size, 16).map_err(|_| Error::BodyTooLarge)
+    }
+
+    /// Reads the trailer section after the last chunk.
+    fn read_trailers(&mut self) -> Result<(), Error> {
+        let mut trailer_bytes = 0;
+        loop {
+            let remaining = self.limits.max_header_bytes.saturating_sub(trailer_bytes);
+            let line = read_line_limited(&mut self.reader, remaining, Error::HeadersTooLarge)?;
+            trailer_bytes += line.len();
+            let line = line.strip_suffix(b"\r\n").ok_or(Error::BadChunk)?;
+            if line.is_empty() {
+                return Ok(());
+            }
+            if self.trailers.len() >= self.limits.max_headers {
+                return Err(Error::HeadersTooLarge);
+            }
+            let line = std::str::from_utf8(line).map_err(|_| Error::BadHeader)?;
+            let (name, value) = line.split_once(':').ok_or(Error::BadHeader)?;
+            if name.ends_with(char::is_whitespace) {
+                return Err(Error::BadHeader);
+            }
+            self.trailers.try_append(name, value.trim())?;
+        }
+    }
+
+    fn read_decoded(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let size = self.read_size()?;
+            if size == 0 {
+                self.read_trailers()?;
+                self.done = true;
+                return Ok(0);
+            }
+            if size > self.limits.max_body_size.saturating_sub(self.decoded) {
+                return Err(Error::BodyTooLarge);
+            }
+            self.remaining = size;
+            self.in_chunk = true;
+        }
+        let max = buf.len().min(self.remaining);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(Error::ConnectionClosed);
+        }
+        self.remaining -= n;
+        self.decoded += n;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> Read for ChunkedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_decoded(buf)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
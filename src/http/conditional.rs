@@ -0,0 +1,260 @@
+use super::{date, HTTPMethod, HeaderMap, Request, Response, StatusCode};
+use std::time::SystemTime;
+
+impl Response {
+    /// Sets a strong `ETag`, for a representation whose bytes only change along with the tag.
+    /// Ranges of the representation are only resumed with `If-Range` against a strong tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains characters that aren't allowed in an entity tag,
+    /// like `"` or whitespace.
+    pub fn etag(mut self, tag: &str) -> Self {
+        assert!(is_opaque_tag(tag), "Invalid entity tag {tag:?}");
+        self.headers.insert("ETag", format!("\"{tag}\""));
+        self
+    }
+
+    /// Sets a weak `ETag`, for a representation that's only semantically equivalent while
+    /// the tag is, e.g. a page that's rendered again with another timestamp.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains characters that aren't allowed in an entity tag.
+    pub fn weak_etag(mut self, tag: &str) -> Self {
+        assert!(is_opaque_tag(tag), "Invalid entity tag {tag:?}");
+        self.headers.insert("ETag", format!("W/\"{tag}\""));
+        self
+    }
+
+    /// Sets `Last-Modified`, truncated to the second like every HTTP date.
+    pub fn last_modified(mut self, time: SystemTime) -> Self {
+        self.headers.insert("Last-Modified", date::format(time));
+        self
+    }
+}
+
+impl Request {
+    /// Returns the precondition headers of the request, like `If-Match`, so a handler
+    /// can evaluate them against the resource before changing it.
+    ///
+    /// The server evaluates them on its own for GET and HEAD, against the validators of
+    /// the handler's response. That response, body included, is built before it's turned
+    /// into a 304, so a handler with an expensive body can call [`Preconditions::evaluate`]
+    /// first. Other methods usually change the resource, so their preconditions have to
+    /// be checked by the handler, before it does.
+    pub fn preconditions(&self) -> Preconditions {
+        let list = |name: &str| {
+            let values: Vec<&str> = self.headers.get_all(name).collect();
+            (!values.is_empty()).then(|| values.join(", "))
+        };
+        Preconditions {
+            get_or_head: matches!(self.method, HTTPMethod::GET | HTTPMethod::HEAD),
+            if_match: list("If-Match"),
+            if_unmodified_since: self.headers.get("If-Unmodified-Since").map(Into::into),
+            if_none_match: list("If-None-Match"),
+            if_modified_since: self.headers.get("If-Modified-Since").map(Into::into),
+        }
+    }
+}
+
+/// The precondition headers of a request, from [`Request::preconditions`].
+///
+/// ```
+/// use blitzkrieg::http::{Request, Response, StatusCode};
+///
+/// fn update(request: Request) -> Response {
+///     // The document's current ETag, or `None` if it doesn't have one.
+///     let etag = Some("\"v1\"");
+///     if let Some(status) = request.preconditions().evaluate(etag, None) {
+///         return Response::new(status);
+///     }
+///     // Only now is the document changed.
+///     Response::new(StatusCode::NO_CONTENT)
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    /// Whether the request is a GET or a HEAD, which get a 304 rather than a 412.
+    get_or_head: bool,
+    if_match: Option<String>,
+    if_unmodified_since: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+impl Preconditions {
+    /// Evaluates the preconditions against the current state of an existing resource,
+    /// in the order of RFC 9110 section 13.2.2. `etag` is its entity tag as it's sent
+    /// in `ETag`, with the quotes, like `"v1"` or `W/"v1"`.
+    ///
+    /// Returns the status to respond with instead of handling the request if they
+    /// aren't met: 304 Not Modified for a GET or HEAD, 412 Precondition Failed
+    /// otherwise, or for a failed `If-Match` or `If-Unmodified-Since`.
+    /// Invalid dates are ignored.
+    pub fn evaluate(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> Option<StatusCode> {
+        let etag = etag.and_then(parse_etag);
+        let last_modified = last_modified.map(date::seconds_since_epoch);
+        self.check(true, etag, last_modified)
+    }
+
+    /// Evaluates the preconditions for a resource that doesn't exist yet, like the
+    /// target of a PUT that creates it. Only `If-Match` can fail then, with a 412.
+    pub fn evaluate_missing(&self) -> Option<StatusCode> {
+        self.check(false, None, None)
+    }
+
+    /// Turns a response into a 304 Not Modified or a 412 Precondition Failed if the
+    /// preconditions aren't met by its `ETag` and `Last-Modified`.
+    ///
+    /// Only successful responses are evaluated, since any other status would have been
+    /// sent without the preconditions too.
+    pub(crate) fn apply(&self, response: Response) -> Response {
+        if !response.status_code.is_success() {
+            return response;
+        }
+        let etag = response.headers.get("ETag").and_then(parse_etag);
+        let last_modified = response.headers.get("Last-Modified").and_then(date::parse);
+        match self.check(true, etag, last_modified) {
+            Some(StatusCode::NOT_MODIFIED) => not_modified(response),
+            Some(_) => failed(response),
+            None => response,
+        }
+    }
+
+    fn check(
+        &self,
+        exists: bool,
+        etag: Option<EntityTag>,
+        last_modified: Option<u64>,
+    ) -> Option<StatusCode> {
+        if let Some(if_match) = &self.if_match {
+            if !exists || !matches_any(if_match, etag, false) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        } else if let Some(since) = self.if_unmodified_since.as_deref().and_then(date::parse) {
+            if last_modified.is_some_and(|modified| modified > since) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        }
+        if let Some(if_none_match) = &self.if_none_match {
+            if exists && matches_any(if_none_match, etag, true) {
+                return match self.get_or_head {
+                    true => Some(StatusCode::NOT_MODIFIED),
+                    false => Some(StatusCode::PRECONDITION_FAILED),
+                };
+            }
+        } else if let Some(since) = self.if_modified_since.as_deref().and_then(date::parse) {
+            if self.get_or_head && last_modified.is_some_and(|modified| modified <= since) {
+                return Some(StatusCode::NOT_MODIFIED);
+            }
+        }
+        None
+    }
+
+    /// Returns whether the request is a GET or a HEAD, whose preconditions the server
+    /// evaluates against the handler's response.
+    pub(crate) fn is_get_or_head(&self) -> bool {
+        self.get_or_head
+    }
+}
+
+/// Returns whether the `If-Range` of a request matches the validators of a response,
+/// which means the ranges can be sent. Weak tags never match, and a date only matches
+/// the exact `Last-Modified`.
+pub(crate) fn if_range_matches(if_range: &str, headers: &HeaderMap) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        let etag = headers.get("ETag").and_then(parse_etag);
+        return match (parse_etag(if_range), etag) {
+            (Some(tag), Some(etag)) => strong_match(tag, etag),
+            _ => false,
+        };
+    }
+    let last_modified = headers.get("Last-Modified").and_then(date::parse);
+    date::parse(if_range).is_some_and(|date| last_modified == Some(date))
+}
+
+/// An entity tag: whether it's weak, and its opaque tag with the quotes.
+type EntityTag<'a> = (bool, &'a str);
+
+/// Returns whether a string can be put between the quotes of an entity tag.
+fn is_opaque_tag(tag: &str) -> bool {
+    tag.bytes()
+        .all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80)
+}
+
+fn parse_etag(value: &str) -> Option<EntityTag<'_>> {
+    let value = value.trim();
+    let (weak, opaque) = match value.strip_prefix("W/") {
+        Some(opaque) => (true, opaque),
+        None => (false, value),
+    };
+    let tag = opaque.strip_prefix('"')?.strip_suffix('"')?;
+    is_opaque_tag(tag).then_some((weak, opaque))
+}
+
+fn strong_match(a: EntityTag, b: EntityTag) -> bool {
+    !a.0 && !b.0 && a.1 == b.1
+}
+
+/// Returns whether a list of entity tags like `"a", W/"b"`, or `*`, matches an entity tag.
+/// `If-Match` uses the strong comparison, `If-None-Match` the weak one.
+/// `*` matches any current representation, so it matches even without a tag.
+fn matches_any(list: &str, etag: Option<EntityTag>, weak_comparison: bool) -> bool {
+    if list.trim() == "*" {
+        return true;
+    }
+    let Some(etag) = etag else {
+        return false;
+    };
+    // Opaque tags can contain commas, so the list is split on the closing quotes.
+    let mut rest = list;
+    while let Some(start) = rest.find('"') {
+        let Some(length) = rest[start + 1..].find('"') else {
+            return false;
+        };
+        let end = start + length + 2;
+        let tag = (rest[..start].ends_with("W/"), &rest[start..end]);
+        let matched = match weak_comparison {
+            true => tag.1 == etag.1,
+            false => strong_match(tag, etag),
+        };
+        if matched {
+            return true;
+        }
+        rest = &rest[end..];
+    }
+    false
+}
+
+/// Turns a response into a 304, keeping the headers that aren't about the content,
+/// like `ETag`, `Cache-Control` and `Vary`.
+fn not_modified(mut response: Response) -> Response {
+    remove_content_headers(&mut response.headers);
+    Response::new(StatusCode::NOT_MODIFIED).headers(response.headers)
+}
+
+fn failed(mut response: Response) -> Response {
+    remove_content_headers(&mut response.headers);
+    Response::new(StatusCode::PRECONDITION_FAILED)
+        .headers(response.headers)
+        .body("Precondition Failed")
+}
+
+/// Removes the headers describing a response's body, which a 304 or 412 doesn't have.
+fn remove_content_headers(headers: &mut HeaderMap) {
+    let names: Vec<String> = headers
+        .iter()
+        .map(|(name, _)| name.to_ascii_lowercase())
+        .filter(|name| name.starts_with("content-") && name != "content-location")
+        .collect();
+    for name in names {
+        headers.remove(&name);
+    }
+    headers.remove("Accept-Ranges");
+}
@@ -0,0 +1,318 @@
+#[cfg(feature = "compression")]
+use super::Body;
+use super::{Error, HeaderMap};
+#[cfg(feature = "compression")]
+use flate2::{read, write};
+#[cfg(feature = "compression")]
+use std::io::{self, Write};
+use std::{io::Read, str::FromStr};
+
+/// The codings request bodies can be sent with, for the `Accept-Encoding` of a 415 response.
+#[cfg(feature = "compression")]
+pub(crate) const ACCEPTED_CODINGS: &str = "gzip, deflate";
+#[cfg(not(feature = "compression"))]
+pub(crate) const ACCEPTED_CODINGS: &str = "identity";
+
+/// A content coding that bodies can be compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentCoding {
+    /// The gzip format.
+    Gzip,
+    /// The zlib format, which HTTP calls `deflate`.
+    Deflate,
+}
+
+impl ContentCoding {
+    /// Returns the name of the coding, as used in `Content-Encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    /// Picks the coding to compress a response with from an `Accept-Encoding` value like
+    /// `gzip;q=0.8, deflate, br`.
+    ///
+    /// The coding with the highest q-value wins, gzip on a tie. A coding that isn't
+    /// listed is only acceptable through `*`, and a q-value of 0 makes it unacceptable.
+    /// Returns `None` if neither gzip nor deflate is acceptable.
+    pub fn negotiate(accept_encoding: &str) -> Option<ContentCoding> {
+        let mut gzip = None;
+        let mut deflate = None;
+        let mut any = None;
+        for item in accept_encoding.split(',') {
+            let mut parameters = item.split(';');
+            let coding = parameters.next().unwrap_or_default().trim();
+            let mut quality = Some(1000);
+            for parameter in parameters {
+                if let Some((name, value)) = parameter.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        quality = parse_quality(value.trim());
+                    }
+                }
+            }
+            // An entry with an invalid q-value is ignored.
+            let Some(quality) = quality else {
+                continue;
+            };
+            match coding.parse() {
+                Ok(Self::Gzip) => gzip = Some(quality),
+                Ok(Self::Deflate) => deflate = Some(quality),
+                Err(_) if coding == "*" => any = Some(quality),
+                Err(_) => {}
+            }
+        }
+        let gzip = gzip.or(any).unwrap_or_default();
+        let deflate = deflate.or(any).unwrap_or_default();
+        match (gzip, deflate) {
+            (0, 0) => None,
+            (gzip, deflate) if gzip >= deflate => Some(Self::Gzip),
+            _ => Some(Self::Deflate),
+        }
+    }
+}
+
+impl FromStr for ContentCoding {
+    type Err = Error;
+
+    /// Parses a coding name, case-insensitively. `x-gzip` is an alias of `gzip`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            _ => Err(Error::UnsupportedContentEncoding),
+        }
+    }
+}
+
+/// Takes the `Content-Encoding` out of request headers, returning its codings in the
+/// order they were applied. `identity` is skipped.
+///
+/// Codings can only be decoded with the `compression` feature, so without it any
+/// coding is unsupported.
+pub(crate) fn content_codings(headers: &mut HeaderMap) -> Result<Vec<ContentCoding>, Error> {
+    if !headers.contains_key("Content-Encoding") {
+        return Ok(vec![]);
+    }
+    let value = headers
+        .get_all("Content-Encoding")
+        .collect::<Vec<_>>()
+        .join(",");
+    let codings = value
+        .split(',')
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+        .map(str::parse)
+        .collect::<Result<Vec<ContentCoding>, Error>>()?;
+    if cfg!(not(feature = "compression")) && !codings.is_empty() {
+        return Err(Error::UnsupportedContentEncoding);
+    }
+    headers.remove("Content-Encoding");
+    Ok(codings)
+}
+
+/// Wraps a reader so it reads the body with its codings undone, the last applied first.
+///
+/// Reading more than `max_size` decoded bytes fails with [`Error::BodyTooLarge`],
+/// however well the body was compressed.
+#[cfg(feature = "compression")]
+pub(crate) fn decoder<'a, R: Read + Send + 'a>(
+    reader: R,
+    codings: &[ContentCoding],
+    max_size: usize,
+) -> Box<dyn Read + Send + 'a> {
+    let mut reader: Box<dyn Read + Send + 'a> = Box::new(reader);
+    if codings.is_empty() {
+        return reader;
+    }
+    for coding in codings.iter().rev() {
+        reader = match coding {
+            ContentCoding::Gzip => Box::new(read::MultiGzDecoder::new(reader)),
+            ContentCoding::Deflate => Box::new(read::ZlibDecoder::new(reader)),
+        };
+    }
+    Box::new(SizeLimit {
+        reader,
+        remaining: max_size as u64,
+    })
+}
+
+/// Without the `compression` feature no coding is accepted, so there's nothing to undo.
+#[cfg(not(feature = "compression"))]
+pub(crate) fn decoder<'a, R: Read + Send + 'a>(
+    reader: R,
+    _codings: &[ContentCoding],
+    _max_size: usize,
+) -> Box<dyn Read + Send + 'a> {
+    Box::new(reader)
+}
+
+/// A reader that fails with [`Error::BodyTooLarge`] rather than read past a size.
+#[cfg(feature = "compression")]
+struct SizeLimit<R> {
+    reader: R,
+    remaining: u64,
+}
+
+#[cfg(feature = "compression")]
+impl<R: Read> Read for SizeLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // One byte past the limit tells a body that's too large from one that just fits.
+        let max = buf.len().min(self.remaining.saturating_add(1) as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Error::BodyTooLarge,
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Decodes a request body by its `Content-Encoding`, so it's parsed like any other body.
+/// The header is removed and `Content-Length` is set to the decoded length.
+///
+/// The decoded body can't be larger than `max_size`, however well it was compressed.
+pub(crate) fn decode(
+    headers: &mut HeaderMap,
+    body: Vec<u8>,
+    max_size: usize,
+) -> Result<Vec<u8>, Error> {
+    let codings = content_codings(headers)?;
+    if codings.is_empty() {
+        return Ok(body);
+    }
+    let mut decoded = vec![];
+    decoder(&body[..], &codings, max_size)
+        .read_to_end(&mut decoded)
+        .map_err(|error| match Error::from(error) {
+            Error::BodyTooLarge => Error::BodyTooLarge,
+            _ => Error::InvalidBody,
+        })?;
+    if headers.contains_key("Content-Length") {
+        headers.insert("Content-Length", decoded.len().to_string());
+    }
+    Ok(decoded)
+}
+
+/// Parses a q-value like `0.5` into thousandths.
+fn parse_quality(value: &str) -> Option<u16> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if !matches!(whole, "0" | "1") || fraction.len() > 3 || !digits(fraction) {
+        return None;
+    }
+    let fraction = format!("{fraction:0<3}").parse::<u16>().ok()?;
+    let quality = whole.parse::<u16>().ok()? * 1000 + fraction;
+    (quality <= 1000).then_some(quality)
+}
+
+/// Compresses a body. Bodies in memory are compressed at once, so their length stays
+/// known, while streamed ones are compressed as they're sent.
+#[cfg(feature = "compression")]
+pub(crate) fn encode(body: Body, coding: ContentCoding, level: u32) -> io::Result<Body> {
+    let level = flate2::Compression::new(level);
+    Ok(match body {
+        Body::Empty => Body::Empty,
+        Body::Bytes(bytes) => {
+            let mut encoder = Encoder::new(vec![], coding, level);
+            encoder.write_all(&bytes)?;
+            Body::Bytes(encoder.finish()?)
+        }
+        Body::Reader { reader, length } => {
+            let reader: Box<dyn Read + Send> = match length {
+                Some(length) => Box::new(reader.take(length)),
+                None => reader,
+            };
+            let encoder: Box<dyn Read + Send> = match coding {
+                ContentCoding::Gzip => Box::new(read::GzEncoder::new(reader, level)),
+                ContentCoding::Deflate => Box::new(read::ZlibEncoder::new(reader, level)),
+            };
+            Body::from_reader(encoder, None)
+        }
+        Body::Chunks(chunks) => Body::from_chunks(EncodedChunks {
+            chunks,
+            encoder: Some(Encoder::new(vec![], coding, level)),
+        }),
+    })
+}
+
+/// A compressing writer for either coding.
+#[cfg(feature = "compression")]
+enum Encoder<W: Write> {
+    Gzip(write::GzEncoder<W>),
+    Deflate(write::ZlibEncoder<W>),
+}
+
+#[cfg(feature = "compression")]
+impl<W: Write> Encoder<W> {
+    fn new(writer: W, coding: ContentCoding, level: flate2::Compression) -> Self {
+        match coding {
+            ContentCoding::Gzip => Self::Gzip(write::GzEncoder::new(writer, level)),
+            ContentCoding::Deflate => Self::Deflate(write::ZlibEncoder::new(writer, level)),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut W {
+        match self {
+            Self::Gzip(encoder) => encoder.get_mut(),
+            Self::Deflate(encoder) => encoder.get_mut(),
+        }
+    }
+
+    /// Writes the end of the stream and returns the writer.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Deflate(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Deflate(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Compresses a chunked body chunk by chunk. Each chunk is flushed through the
+/// encoder, so a client receives it as soon as it's produced.
+#[cfg(feature = "compression")]
+struct EncodedChunks {
+    chunks: Box<dyn Iterator<Item = Vec<u8>> + Send>,
+    /// The encoder, until the end of the stream is written.
+    encoder: Option<Encoder<Vec<u8>>>,
+}
+
+#[cfg(feature = "compression")]
+impl Iterator for EncodedChunks {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let encoder = self.encoder.as_mut()?;
+        for chunk in self.chunks.by_ref() {
+            // Writing to a Vec doesn't fail.
+            encoder.write_all(&chunk).ok()?;
+            encoder.flush().ok()?;
+            let output = std::mem::take(encoder.get_mut());
+            if !output.is_empty() {
+                return Some(output);
+            }
+        }
+        self.encoder.take()?.finish().ok()
+    }
+}
@@ -0,0 +1,106 @@
+use super::StatusCode;
+use std::{fmt, io};
+
+/// Errors that can happen while reading or parsing an HTTP Request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The client closed the connection before sending a request.
+    ConnectionClosed,
+    /// Reading from the connection failed.
+    Io(io::ErrorKind),
+    /// The client took too long to send the request.
+    Timeout,
+    /// The request line isn't of the form `METHOD target HTTP/x.y`.
+    MalformedRequestLine,
+    /// The request line is longer than the server accepts.
+    UriTooLong,
+    /// A header line is malformed.
+    BadHeader,
+    /// The headers are larger than the server accepts.
+    HeadersTooLarge,
+    /// The Content-Length header isn't a valid number.
+    InvalidContentLength,
+    /// The body is larger than the server accepts.
+    BodyTooLarge,
+    /// The chunked body is malformed.
+    BadChunk,
+    /// The Transfer-Encoding isn't supported.
+    UnsupportedTransferEncoding,
+    /// The Content-Encoding of the body isn't supported.
+    UnsupportedContentEncoding,
+    /// The body doesn't match its declared Content-Type.
+    InvalidBody,
+    /// The multipart/form-data body is malformed.
+    BadMultipart,
+    /// The request method isn't supported.
+    #[deprecated(note = "any valid method token is parsed, so this error isn't returned anymore")]
+    UnsupportedMethod,
+    /// The HTTP version of the request isn't supported.
+    UnsupportedVersion,
+}
+
+impl Error {
+    /// Returns the status code the server should respond with,
+    /// or `None` if the connection should be closed without a response.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            Self::ConnectionClosed | Self::Io(_) => None,
+            Self::MalformedRequestLine
+            | Self::BadHeader
+            | Self::InvalidContentLength
+            | Self::InvalidBody
+            | Self::BadChunk
+            | Self::BadMultipart => Some(StatusCode::BAD_REQUEST),
+            Self::Timeout => Some(StatusCode::REQUEST_TIMEOUT),
+            Self::BodyTooLarge => Some(StatusCode::CONTENT_TOO_LARGE),
+            Self::UriTooLong => Some(StatusCode::URI_TOO_LONG),
+            Self::HeadersTooLarge => Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            #[allow(deprecated)]
+            Self::UnsupportedMethod | Self::UnsupportedTransferEncoding => {
+                Some(StatusCode::NOT_IMPLEMENTED)
+            }
+            Self::UnsupportedContentEncoding => Some(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            Self::UnsupportedVersion => Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ConnectionClosed => write!(f, "Connection closed"),
+            Self::Io(kind) => write!(f, "I/O error: {kind}"),
+            Self::Timeout => write!(f, "Request timed out"),
+            Self::MalformedRequestLine => write!(f, "Malformed request line"),
+            Self::UriTooLong => write!(f, "Request line too long"),
+            Self::BadHeader => write!(f, "Malformed header"),
+            Self::HeadersTooLarge => write!(f, "Request headers too large"),
+            Self::InvalidContentLength => write!(f, "Invalid Content-Length value"),
+            Self::BodyTooLarge => write!(f, "Request body too large"),
+            Self::BadChunk => write!(f, "Malformed chunked body"),
+            Self::UnsupportedTransferEncoding => write!(f, "Unsupported Transfer-Encoding"),
+            Self::UnsupportedContentEncoding => write!(f, "Unsupported Content-Encoding"),
+            Self::InvalidBody => write!(f, "Invalid request body"),
+            Self::BadMultipart => write!(f, "Error while parsing form data"),
+            #[allow(deprecated)]
+            Self::UnsupportedMethod => write!(f, "Unknown HTTP method"),
+            Self::UnsupportedVersion => write!(f, "HTTP version not supported"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        // Readers like the chunked decoder wrap parsing errors in I/O errors.
+        if let Some(error) = error.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            return error.clone();
+        }
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => Self::ConnectionClosed,
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Self::Timeout,
+            kind => Self::Io(kind),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use super::Error;
+use std::collections::HashMap;
+
+/// HTTP header map.
+///
+/// Header names are matched case-insensitively but keep the case they were inserted with.
+/// A name can have several values, and headers keep their insertion order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    /// Creates an empty HeaderMap.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first value of a header.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of a header, in insertion order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns whether the map contains a header.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets a header, replacing all of its values.
+    /// Returns the previous first value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name isn't a valid header name or the value contains control characters,
+    /// which could be used to inject headers.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.try_insert(name, value).unwrap()
+    }
+
+    /// Adds a value to a header, keeping its existing values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name isn't a valid header name or the value contains control characters.
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.try_append(name, value).unwrap()
+    }
+
+    /// Sets a header, replacing all of its values, or fails if the name or value is invalid.
+    pub fn try_insert(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Option<String>, Error> {
+        let (name, value) = validate(name.into(), value.into())?;
+        let position = self
+            .entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(&name));
+        let Some(position) = position else {
+            self.entries.push((name, value));
+            return Ok(None);
+        };
+        let previous = std::mem::replace(&mut self.entries[position], (name, value));
+        let mut index = 0;
+        self.entries.retain(|(key, _)| {
+            index += 1;
+            index - 1 <= position || !key.eq_ignore_ascii_case(&previous.0)
+        });
+        Ok(Some(previous.1))
+    }
+
+    /// Adds a value to a header, or fails if the name or value is invalid.
+    pub fn try_append(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), Error> {
+        let entry = validate(name.into(), value.into())?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Removes every value of a header, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self.get(name).map(|value| value.to_string());
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        first
+    }
+
+    /// Iterates over the headers in insertion order.
+    /// A header with several values is yielded once per value.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of header values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Checks that a header name is a token and that a value has no control characters.
+fn validate(name: String, value: String) -> Result<(String, String), Error> {
+    if !is_token(&name) {
+        return Err(Error::BadHeader);
+    }
+    let valid_value = value
+        .bytes()
+        .all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f));
+    if !valid_value {
+        return Err(Error::BadHeader);
+    }
+    Ok((name, value))
+}
+
+/// Returns whether a string is a token as defined by RFC 9110.
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    /// Collects headers, appending repeated names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a name or value is invalid.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = HeaderMap::new();
+        for (name, value) in iter {
+            headers.append(name, value);
+        }
+        headers
+    }
+}
+
+impl From<HashMap<String, String>> for HeaderMap {
+    fn from(headers: HashMap<String, String>) -> Self {
+        headers.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+/// Splits a header value like `form-data; name="field"; filename*=UTF-8''a.txt`
+/// into its main value and its parameters.
+///
+/// Parameter names are lowercased and quoted values are unescaped.
+pub(crate) fn parse_parameters(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = split_unquoted(value, ';').into_iter();
+    let main = parts.next().unwrap_or_default().trim().to_string();
+    let parameters = parts
+        .filter_map(|part| {
+            let (name, value) = part.split_once('=')?;
+            let name = name.trim().to_ascii_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            Some((name, unquote(value.trim())))
+        })
+        .collect();
+    (main, parameters)
+}
+
+/// Splits a string on a separator that's outside of quoted strings.
+pub(crate) fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Removes the quotes and backslash escapes of a quoted string.
+/// Values that aren't quoted are returned as they are.
+pub(crate) fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"') else {
+        return value.to_string();
+    };
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
@@ -1,7 +1,41 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+mod body;
+mod chunked;
+mod conditional;
+mod date;
+mod encoding;
+mod error;
+mod header;
+mod multipart;
 mod parser;
+mod range;
+mod status;
+mod url;
 
-#[derive(Debug, PartialEq)]
+pub use body::Body;
+pub use conditional::Preconditions;
+#[cfg(feature = "compression")]
+pub(crate) use encoding::encode;
+pub use encoding::ContentCoding;
+pub(crate) use encoding::{content_codings, decode, decoder, ACCEPTED_CODINGS};
+pub use error::Error;
+pub use header::HeaderMap;
+pub use multipart::{Multipart, Part, TempFile};
+pub(crate) use parser::BodyReader;
+pub use range::ByteRanges;
+pub use status::StatusCode;
+pub(crate) use url::path_segments;
+pub use url::{parse_query, percent_decode};
+
+/// HTTP request method.
+///
+/// Methods that aren't defined by RFC 9110, like WebDAV's `PROPFIND`, are kept as
+/// [`HTTPMethod::Extension`]. Method names are case-sensitive.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum HTTPMethod {
     GET,
     POST,
@@ -10,13 +44,46 @@ pub enum HTTPMethod {
     DELETE,
     HEAD,
     OPTIONS,
+    TRACE,
+    CONNECT,
+    /// Any other method, which is always a valid token.
+    Extension(String),
 }
 
-// Error type for HTTPMethod
+impl HTTPMethod {
+    /// Returns the method's name.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::GET => "GET",
+            Self::POST => "POST",
+            Self::PUT => "PUT",
+            Self::PATCH => "PATCH",
+            Self::DELETE => "DELETE",
+            Self::HEAD => "HEAD",
+            Self::OPTIONS => "OPTIONS",
+            Self::TRACE => "TRACE",
+            Self::CONNECT => "CONNECT",
+            Self::Extension(method) => method,
+        }
+    }
+
+    /// Returns whether the method is read-only, as defined by RFC 9110.
+    /// Extension methods are assumed not to be.
+    pub fn is_safe(&self) -> bool {
+        matches!(self, Self::GET | Self::HEAD | Self::OPTIONS | Self::TRACE)
+    }
+
+    /// Returns whether repeating the method has the same effect as sending it once,
+    /// as defined by RFC 9110. Extension methods are assumed not to be.
+    pub fn is_idempotent(&self) -> bool {
+        self.is_safe() || matches!(self, Self::PUT | Self::DELETE)
+    }
+}
 
 impl FromStr for HTTPMethod {
-    type Err = String;
+    type Err = Error;
     /// Creates an HTTP method enum value from a str.
+    /// Fails if the method isn't a valid token.
     fn from_str(input: &str) -> Result<HTTPMethod, Self::Err> {
         match input {
             "GET" => Ok(HTTPMethod::GET),
@@ -26,21 +93,79 @@ impl FromStr for HTTPMethod {
             "DELETE" => Ok(HTTPMethod::DELETE),
             "HEAD" => Ok(HTTPMethod::HEAD),
             "OPTIONS" => Ok(HTTPMethod::OPTIONS),
-            _ => Err("Unknown HTTP method".to_string()),
+            "TRACE" => Ok(HTTPMethod::TRACE),
+            "CONNECT" => Ok(HTTPMethod::CONNECT),
+            _ if header::is_token(input) => Ok(HTTPMethod::Extension(input.to_string())),
+            _ => Err(Error::MalformedRequestLine),
         }
     }
 }
 
 impl std::fmt::Display for HTTPMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// HTTP protocol version of a request.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub enum Version {
+    Http10,
+    #[default]
+    Http11,
+}
+
+impl FromStr for Version {
+    type Err = Error;
+    /// Parses the version at the end of a request line, like `HTTP/1.1`.
+    /// Later 1.x versions are read as HTTP/1.1, which they're compatible with.
+    fn from_str(input: &str) -> Result<Version, Self::Err> {
+        let (major, minor) = input
+            .strip_prefix("HTTP/")
+            .and_then(|version| version.split_once('.'))
+            .ok_or(Error::MalformedRequestLine)?;
+        let is_digit = |s: &str| s.len() == 1 && s.as_bytes()[0].is_ascii_digit();
+        if !is_digit(major) || !is_digit(minor) {
+            return Err(Error::MalformedRequestLine);
+        }
+        match (major, minor) {
+            ("1", "0") => Ok(Version::Http10),
+            ("1", _) => Ok(Version::Http11),
+            _ => Err(Error::UnsupportedVersion),
+        }
+    }
+}
+
+impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Self::GET => write!(f, "GET"),
-            Self::POST => write!(f, "POST"),
-            Self::PUT => write!(f, "PUT"),
-            Self::PATCH => write!(f, "PATCH"),
-            Self::DELETE => write!(f, "DELETE"),
-            Self::HEAD => write!(f, "HEAD"),
-            Self::OPTIONS => write!(f, "OPTIONS"),
+            Self::Http10 => write!(f, "HTTP/1.0"),
+            Self::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+/// Limits enforced while reading a request, so a client can't exhaust the server's memory.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Maximum length of the request line in bytes. Longer lines get a 414 response.
+    pub max_request_line: usize,
+    /// Maximum total size of the headers in bytes. Larger headers get a 431 response.
+    pub max_header_bytes: usize,
+    /// Maximum number of headers. More headers get a 431 response.
+    pub max_headers: usize,
+    /// Maximum size of the body in bytes. Larger bodies get a 413 response.
+    /// A body sent with a `Content-Encoding` can't be larger once decoded either.
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_request_line: 8 * 1024,
+            max_header_bytes: 64 * 1024,
+            max_headers: 100,
+            max_body_size: 10 * 1024 * 1024,
         }
     }
 }
@@ -49,6 +174,25 @@ impl std::fmt::Display for HTTPMethod {
 pub struct FormdataText {
     pub name: String,
     pub value: String,
+    /// The media type of the part's `Content-Type`, without its parameters.
+    pub content_type: Option<String>,
+    /// The `charset` parameter of the part's `Content-Type`.
+    pub charset: Option<String>,
+    /// Every header of the part. Empty for `application/x-www-form-urlencoded` fields.
+    pub headers: HeaderMap,
+}
+
+impl FormdataText {
+    /// Creates a text field without any part headers.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            content_type: None,
+            charset: None,
+            headers: HeaderMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -56,7 +200,16 @@ pub struct FormdataFile {
     pub name: String,
     pub file_name: String,
     pub content: Vec<u8>,
+    /// The media type of the part's `Content-Type`, without its parameters.
+    pub content_type: Option<String>,
+    /// The `charset` parameter of the part's `Content-Type`.
+    pub charset: Option<String>,
+    /// Every header of the part.
+    pub headers: HeaderMap,
 }
+
+/// A single part of a `multipart/form-data` body.
+#[derive(Debug, PartialEq)]
 pub enum Formdata {
     FormdataText(FormdataText),
     FormdataFile(FormdataFile),
@@ -65,10 +218,26 @@ pub enum Formdata {
 #[derive(Debug, PartialEq)]
 /// Formdata body struct.
 pub struct FormdataBody {
-    /// Formdata text fields.
-    pub fields: Option<Vec<FormdataText>>,
-    /// Formdata files.
-    pub files: Option<Vec<FormdataFile>>,
+    /// The fields and files, in the order they were sent.
+    pub parts: Vec<Formdata>,
+}
+
+impl FormdataBody {
+    /// Returns the text fields, in the order they were sent.
+    pub fn fields(&self) -> impl Iterator<Item = &FormdataText> {
+        self.parts.iter().filter_map(|part| match part {
+            Formdata::FormdataText(field) => Some(field),
+            Formdata::FormdataFile(_) => None,
+        })
+    }
+
+    /// Returns the files, in the order they were sent.
+    pub fn files(&self) -> impl Iterator<Item = &FormdataFile> {
+        self.parts.iter().filter_map(|part| match part {
+            Formdata::FormdataFile(file) => Some(file),
+            Formdata::FormdataText(_) => None,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -83,65 +252,255 @@ pub struct File {
 pub enum BodyType {
     Text(String),
     FormdataBody(FormdataBody),
+    /// An `application/x-www-form-urlencoded` body, decoded into name/value pairs.
+    UrlEncoded(Vec<FormdataText>),
     File(File),
+    /// A `multipart/form-data` body that's read from the connection as it's consumed.
+    Multipart(Multipart),
+}
+
+impl BodyType {
+    /// Returns the text fields of a form, whether it was sent as
+    /// `multipart/form-data` or `application/x-www-form-urlencoded`.
+    pub fn form_fields(&self) -> Vec<&FormdataText> {
+        match self {
+            Self::FormdataBody(body) => body.fields().collect(),
+            Self::UrlEncoded(fields) => fields.iter().collect(),
+            _ => vec![],
+        }
+    }
 }
 #[derive(Debug)]
 /// HTTP Request struct.
 pub struct Request {
     pub method: HTTPMethod,
+    /// The protocol version from the request line.
+    pub version: Version,
+    /// The percent-decoded path, without the query string.
     pub path: String,
-    pub headers: HashMap<String, String>,
+    /// The path as it was sent, still percent-encoded. Unlike in `path`, an encoded
+    /// `/` can be told from one that separates segments, so routing splits this one.
+    pub raw_path: String,
+    /// The raw query string, without the `?`.
+    pub raw_query: Option<String>,
+    /// The decoded query parameters, in their original order.
+    pub query_params: Vec<(String, String)>,
+    pub headers: HeaderMap,
     pub body: Option<BodyType>,
+    /// Path parameters extracted by the router, e.g. `id` for `/users/:id`.
+    pub params: HashMap<String, String>,
+    /// Trailer fields sent after a chunked body.
+    pub trailers: HeaderMap,
+}
+
+impl Request {
+    /// Returns the first value of a query parameter.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query_params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of a query parameter, e.g. for `?tag=a&tag=b`.
+    pub fn query_all(&self, name: &str) -> Vec<&str> {
+        self.query_params
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Returns whether the client wants the connection kept open after the response.
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`,
+    /// HTTP/1.0 ones only if it sends `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.headers
+                .get_all("Connection")
+                .flat_map(|value| value.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case(option))
+        };
+        match self.version {
+            Version::Http10 => has_option("keep-alive") && !has_option("close"),
+            Version::Http11 => !has_option("close"),
+        }
+    }
+
+    /// Returns the value of a path parameter extracted by the router.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|value| value.as_str())
+    }
 }
 
 #[derive(Debug)]
 /// HTTP Response struct.
 pub struct Response {
-    pub status_code: u16,
-    pub headers: Option<HashMap<String, String>>,
-    pub body: Option<Vec<u8>>,
+    pub status_code: StatusCode,
+    /// A reason phrase sent instead of the status code's canonical one.
+    pub reason: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Body,
 }
 
 impl Response {
     /// Creates an HTTP Response.
-    pub fn new(status_code: u16) -> Self {
+    pub fn new(status_code: StatusCode) -> Self {
         Self {
             status_code,
-            headers: None,
-            body: None,
+            reason: None,
+            headers: HeaderMap::new(),
+            body: Body::Empty,
         }
     }
 
     /// Set reponse headers.
-    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
-        self.headers = Some(headers);
+    pub fn headers(mut self, headers: impl Into<HeaderMap>) -> Self {
+        self.headers = headers.into();
+        self
+    }
+
+    /// Set a reponse header, replacing its previous values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or value is invalid, see [`HeaderMap::insert`].
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Set a custom reason phrase for the status line.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reason contains control characters, which could be used to inject headers.
+    pub fn reason(mut self, reason: &str) -> Self {
+        let valid = reason
+            .bytes()
+            .all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f));
+        assert!(valid, "Invalid reason phrase {reason:?}");
+        self.reason = Some(reason.to_string());
         self
     }
 
     /// Set reponse body.
     pub fn body(mut self, body: &str) -> Self {
-        self.body = Some(body.as_bytes().to_vec());
+        self.body = Body::from(body);
         self
     }
 
-    /// Turns an HTTP Response into bytes.
-    pub fn into_bytes(self) -> Vec<u8> {
-        let mut res = String::from("HTTP/1.1 ");
-        res.push_str(&self.status_code.to_string());
-        res.push_str("\r\n");
-        let headers = self.headers.unwrap_or_default();
-        for (key, value) in headers {
+    /// Set reponse body from bytes.
+    pub fn bytes(mut self, bytes: Vec<u8>) -> Self {
+        self.body = Body::Bytes(bytes);
+        self
+    }
+
+    /// Set reponse body from a reader, which is streamed to the client.
+    /// Pass the length if it's known, otherwise the body is sent chunked.
+    pub fn reader<R: Read + Send + 'static>(mut self, reader: R, length: Option<u64>) -> Self {
+        self.body = Body::from_reader(reader, length);
+        self
+    }
+
+    /// Set reponse body from an iterator of chunks, which are sent as they're produced.
+    pub fn chunks<I>(mut self, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        self.body = Body::from_chunks(chunks);
+        self
+    }
+
+    /// Writes an HTTP Response to a writer, streaming its body.
+    pub fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        self.write_for(writer, Version::Http11, true, false)
+            .map(|_| ())
+    }
+
+    /// Writes an HTTP Response for a client speaking `version`, announcing whether the
+    /// connection stays open. It's closed anyway if the response has `Connection: close`
+    /// or if the body has to be delimited by closing it.
+    /// Returns whether the connection can be kept open.
+    ///
+    /// The framing headers are derived from the body: handler-set `Content-Length` and
+    /// `Transfer-Encoding` are replaced, and 1xx, 204 and 304 responses never have a body.
+    /// Responses to HEAD requests keep the framing headers the body would have had,
+    /// but the body itself isn't sent.
+    pub(crate) fn write_for<W: Write>(
+        mut self,
+        writer: &mut W,
+        version: Version,
+        keep_alive: bool,
+        head: bool,
+    ) -> io::Result<bool> {
+        let code = self.status_code.as_u16();
+        let has_body = !self.status_code.is_informational() && code != 204 && code != 304;
+        if !has_body {
+            self.body = Body::Empty;
+        }
+        let length = self.body.length();
+        // HTTP/1.0 clients don't know chunked bodies, so the end of the connection ends the body.
+        let close_delimited = length.is_none() && version == Version::Http10;
+        let close_requested = self.headers.remove("Connection").is_some_and(|value| {
+            value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("close"))
+        });
+        // A HEAD response has no body, so it never needs the connection closed to end one.
+        let keep_alive = keep_alive && !close_requested && (head || !close_delimited);
+        let handler_length = self.headers.remove("Content-Length");
+        self.headers.remove("Transfer-Encoding");
+        let reason = match &self.reason {
+            Some(reason) => reason,
+            None => self.status_code.canonical_reason().unwrap_or_default(),
+        };
+        let mut res = format!("HTTP/1.1 {code} {reason}\r\n");
+        if !self.headers.contains_key("Date") {
+            res.push_str(&format!("Date: {}\r\n", date::now()));
+        }
+        for (key, value) in &self.headers {
             res.push_str(&format!("{}: {}\r\n", key, value));
         }
-        res.push_str("Connection: keep-alive\r\n");
+        match keep_alive {
+            true => res.push_str("Connection: keep-alive\r\n"),
+            false => res.push_str("Connection: close\r\n"),
+        }
         res.push_str("Server: Blitzkrieg\r\n");
-        if let Some(mut body) = self.body {
-            res.push_str(&format!("Content-Length: {}\r\n", body.len()));
-            res.push_str("\r\n");
-            let mut res = res.as_bytes().to_owned();
-            res.append(&mut body);
-            return res;
-        }
-        res.as_bytes().to_owned()
+        match length {
+            _ if !has_body => {}
+            // A HEAD handler can announce the length of a body it didn't produce.
+            Some(0) if head && matches!(self.body, Body::Empty) => {
+                let length = handler_length.and_then(|length| length.parse::<u64>().ok());
+                res.push_str(&format!(
+                    "Content-Length: {}\r\n",
+                    length.unwrap_or_default()
+                ));
+            }
+            Some(length) => res.push_str(&format!("Content-Length: {}\r\n", length)),
+            None if close_delimited => {}
+            None => res.push_str("Transfer-Encoding: chunked\r\n"),
+        }
+        res.push_str("\r\n");
+        writer.write_all(res.as_bytes())?;
+        if head {
+            return Ok(keep_alive);
+        }
+        match length.is_none() && !close_delimited {
+            true => self.body.write_chunked_to(writer)?,
+            false => self.body.write_to(writer)?,
+        }
+        Ok(keep_alive)
+    }
+
+    /// Turns an HTTP Response into bytes.
+    /// A body read from a failing reader is cut short, use [`Response::write_to`] to handle the error.
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut res = vec![];
+        if let Err(err) = self.write_to(&mut res) {
+            println!("Error in reading response body. {err}");
+        }
+        res
     }
 }
@@ -0,0 +1,476 @@
+use super::{
+    header::parse_parameters, url::percent_decode_bytes, Error, Formdata, FormdataBody,
+    FormdataFile, FormdataText, HeaderMap,
+};
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Maximum size of the headers of a single part.
+const MAX_PART_HEADER_BYTES: usize = 8 * 1024;
+/// Maximum number of headers of a single part.
+const MAX_PART_HEADERS: usize = 32;
+/// How much is read from the underlying reader at once.
+const READ_SIZE: usize = 8 * 1024;
+
+/// Returns the boundary parameter of a `multipart/form-data` Content-Type.
+pub(crate) fn boundary(content_type: &str) -> Result<String, Error> {
+    let (_, parameters) = parse_parameters(content_type);
+    let boundary = parameters
+        .into_iter()
+        .find(|(name, _)| name == "boundary")
+        .map(|(_, value)| value)
+        .ok_or(Error::BadMultipart)?;
+    // RFC 2046 limits boundaries to 70 characters.
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(Error::BadMultipart);
+    }
+    Ok(boundary)
+}
+
+/// The headers of a multipart part.
+#[derive(Debug)]
+pub(crate) struct PartHeaders {
+    /// The name of the form field.
+    pub(crate) name: String,
+    /// The file name, if the part is a file.
+    pub(crate) file_name: Option<String>,
+    /// The media type of the part's `Content-Type`, without its parameters.
+    pub(crate) content_type: Option<String>,
+    /// The `charset` parameter of the part's `Content-Type`.
+    pub(crate) charset: Option<String>,
+    /// All headers of the part.
+    pub(crate) headers: HeaderMap,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    /// Before the first delimiter.
+    Preamble,
+    /// Right after a delimiter, before the part's headers.
+    Delimiter,
+    /// Inside a part's data.
+    Data,
+    /// After the closing delimiter.
+    Done,
+}
+
+/// Reads a `multipart/form-data` body part by part,
+/// holding at most a few kilobytes of it in memory.
+pub(crate) struct MultipartReader<R> {
+    reader: R,
+    /// Bytes read from the reader but not consumed yet.
+    buffer: Vec<u8>,
+    /// `\r\n--` followed by the boundary.
+    delimiter: Vec<u8>,
+    state: State,
+}
+
+impl<R: BufRead> MultipartReader<R> {
+    pub(crate) fn new(reader: R, boundary: &str) -> Self {
+        Self {
+            reader,
+            // The first delimiter may be at the very start of the body, without a preceding CRLF.
+            buffer: b"\r\n".to_vec(),
+            delimiter: [b"\r\n--", boundary.as_bytes()].concat(),
+            state: State::Preamble,
+        }
+    }
+
+    /// Moves to the next part, skipping what's left of the current one.
+    /// Returns `None` after the closing delimiter.
+    pub(crate) fn next_part(&mut self) -> Result<Option<PartHeaders>, Error> {
+        loop {
+            match self.state {
+                State::Done => return Ok(None),
+                State::Preamble | State::Data => {
+                    let mut sink = [0; READ_SIZE];
+                    while self.read_data(&mut sink)? > 0 {}
+                }
+                State::Delimiter => {
+                    let Some(part) = self.read_part_headers()? else {
+                        self.state = State::Done;
+                        return Ok(None);
+                    };
+                    self.state = State::Data;
+                    match part {
+                        Some(part) => return Ok(Some(part)),
+                        // Parts that aren't form fields are skipped.
+                        None => continue,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads data of the current part, or of the preamble.
+    /// Returns 0 at the end of the part.
+    pub(crate) fn read_data(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        if !matches!(self.state, State::Preamble | State::Data) || out.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(i) = find(&self.buffer, &self.delimiter) {
+                if i == 0 {
+                    self.buffer.drain(..self.delimiter.len());
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                return Ok(self.take(i.min(out.len()), out));
+            }
+            // The end of the buffer might be the start of a delimiter, so it's kept.
+            let safe = (self.buffer.len() + 1).saturating_sub(self.delimiter.len());
+            if safe > 0 {
+                return Ok(self.take(safe.min(out.len()), out));
+            }
+            if !self.fill()? {
+                // The body ended before the closing delimiter.
+                return Err(Error::BadMultipart);
+            }
+        }
+    }
+
+    /// Reads what follows a delimiter: either the end of the body, or a part's headers.
+    /// Returns `None` at the closing delimiter, and `Some(None)` for a part without a field name.
+    fn read_part_headers(&mut self) -> Result<Option<Option<PartHeaders>>, Error> {
+        while self.buffer.len() < 2 {
+            if !self.fill()? {
+                return Err(Error::BadMultipart);
+            }
+        }
+        if self.buffer.starts_with(b"--") {
+            // The epilogue after the closing delimiter is ignored.
+            return Ok(None);
+        }
+        // Transport padding may follow the boundary.
+        let line = self.read_line(MAX_PART_HEADER_BYTES)?;
+        if !line.iter().all(|b| b" \t\r\n".contains(b)) {
+            return Err(Error::BadMultipart);
+        }
+        let mut headers = HeaderMap::new();
+        let mut header_bytes = 0;
+        loop {
+            let remaining = MAX_PART_HEADER_BYTES.saturating_sub(header_bytes);
+            let line = self.read_line(remaining)?;
+            header_bytes += line.len();
+            let line = line.strip_suffix(b"\r\n").ok_or(Error::BadMultipart)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() >= MAX_PART_HEADERS {
+                return Err(Error::BadMultipart);
+            }
+            let line = String::from_utf8_lossy(line);
+            let (name, value) = line.split_once(':').ok_or(Error::BadMultipart)?;
+            headers
+                .try_append(name, value.trim())
+                .map_err(|_| Error::BadMultipart)?;
+        }
+        let disposition = headers.get("Content-Disposition").unwrap_or_default();
+        let (kind, parameters) = parse_parameters(disposition);
+        let parameter = |name: &str| {
+            parameters
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        let Some(name) = parameter("name") else {
+            return Ok(Some(None));
+        };
+        if !kind.eq_ignore_ascii_case("form-data") {
+            return Ok(Some(None));
+        }
+        let file_name = parameter("filename*")
+            .and_then(|value| decode_extended(&value))
+            .or_else(|| parameter("filename"));
+        let (content_type, charset) = match headers.get("Content-Type") {
+            Some(value) => {
+                let (media_type, parameters) = parse_parameters(value);
+                let charset = parameters
+                    .into_iter()
+                    .find(|(key, _)| key == "charset")
+                    .map(|(_, value)| value);
+                (Some(media_type), charset)
+            }
+            None => (None, None),
+        };
+        Ok(Some(Some(PartHeaders {
+            name,
+            file_name,
+            content_type,
+            charset,
+            headers,
+        })))
+    }
+
+    /// Reads a line including its CRLF, from the buffer and then from the reader.
+    fn read_line(&mut self, max: usize) -> Result<Vec<u8>, Error> {
+        let mut searched = 0;
+        loop {
+            if let Some(i) = self.buffer[searched..].iter().position(|&b| b == b'\n') {
+                let end = searched + i + 1;
+                if end > max {
+                    return Err(Error::BadMultipart);
+                }
+                return Ok(self.buffer.drain(..end).collect());
+            }
+            searched = self.buffer.len();
+            if searched > max || !self.fill()? {
+                return Err(Error::BadMultipart);
+            }
+        }
+    }
+
+    /// Moves up to `n` buffered bytes to `out`.
+    fn take(&mut self, n: usize, out: &mut [u8]) -> usize {
+        out[..n].copy_from_slice(&self.buffer[..n]);
+        self.buffer.drain(..n);
+        n
+    }
+
+    /// Reads more bytes into the buffer. Returns false at the end of the body.
+    fn fill(&mut self) -> Result<bool, Error> {
+        let available = self.reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(false);
+        }
+        let n = available.len().min(READ_SIZE);
+        self.buffer.extend_from_slice(&available[..n]);
+        self.reader.consume(n);
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for MultipartReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_data(buf)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+/// A `multipart/form-data` body streamed from the connection.
+///
+/// Parts are read one at a time as they arrive, so the size of an upload doesn't affect
+/// the memory used to receive it. The server hands these out instead of a
+/// [`super::FormdataBody`] when [`crate::server::ServerConfig::stream_multipart`] is set.
+pub struct Multipart {
+    reader: MultipartReader<BufReader<Box<dyn Read + Send>>>,
+}
+
+impl Multipart {
+    /// Creates a Multipart reading a body delimited by `boundary`.
+    pub fn new<R: Read + Send + 'static>(reader: R, boundary: &str) -> Self {
+        let reader: Box<dyn Read + Send> = Box::new(reader);
+        Self {
+            reader: MultipartReader::new(BufReader::new(reader), boundary),
+        }
+    }
+
+    /// Returns the next part, skipping whatever wasn't read of the previous one.
+    /// Returns `None` once every part was read.
+    pub fn next_part(&mut self) -> Result<Option<Part<'_>>, Error> {
+        let Some(headers) = self.reader.next_part()? else {
+            return Ok(None);
+        };
+        Ok(Some(Part {
+            headers,
+            reader: &mut self.reader,
+        }))
+    }
+}
+
+impl fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Multipart").finish_non_exhaustive()
+    }
+}
+
+/// A single part of a streamed [`Multipart`] body.
+///
+/// Its content is read through [`Read`], or with one of the helpers that cap its size.
+pub struct Part<'a> {
+    headers: PartHeaders,
+    reader: &'a mut MultipartReader<BufReader<Box<dyn Read + Send>>>,
+}
+
+impl Part<'_> {
+    /// Returns the name of the form field.
+    pub fn name(&self) -> &str {
+        &self.headers.name
+    }
+
+    /// Returns the file name, if the part is a file.
+    pub fn file_name(&self) -> Option<&str> {
+        self.headers.file_name.as_deref()
+    }
+
+    /// Returns the media type of the part's `Content-Type`, without its parameters.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.content_type.as_deref()
+    }
+
+    /// Returns the `charset` parameter of the part's `Content-Type`.
+    pub fn charset(&self) -> Option<&str> {
+        self.headers.charset.as_deref()
+    }
+
+    /// Returns every header of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers.headers
+    }
+
+    /// Reads the content as text, failing with [`Error::BodyTooLarge`]
+    /// if it's longer than `max_size` bytes.
+    pub fn text(&mut self, max_size: u64) -> Result<String, Error> {
+        let mut content = vec![];
+        self.save_to(&mut content, max_size)?;
+        Ok(String::from_utf8_lossy(&content).into_owned())
+    }
+
+    /// Copies the content to `writer`, returning its size.
+    /// Fails with [`Error::BodyTooLarge`] once more than `max_size` bytes were read.
+    pub fn save_to<W: Write>(&mut self, writer: &mut W, max_size: u64) -> Result<u64, Error> {
+        let mut buffer = [0; READ_SIZE];
+        let mut size = 0;
+        loop {
+            let n = self.reader.read_data(&mut buffer)?;
+            if n == 0 {
+                return Ok(size);
+            }
+            size += n as u64;
+            if size > max_size {
+                return Err(Error::BodyTooLarge);
+            }
+            writer.write_all(&buffer[..n])?;
+        }
+    }
+
+    /// Copies the content to a new temporary file, which is deleted when it's dropped.
+    /// Fails with [`Error::BodyTooLarge`] once more than `max_size` bytes were read.
+    pub fn save_to_temp(&mut self, max_size: u64) -> Result<TempFile, Error> {
+        let mut temp_file = TempFile::create()?;
+        let mut file = io::BufWriter::new(fs::File::options().write(true).open(&temp_file.path)?);
+        temp_file.size = self.save_to(&mut file, max_size)?;
+        file.flush()?;
+        Ok(temp_file)
+    }
+}
+
+impl Read for Part<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl fmt::Debug for Part<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A file in the system's temporary directory, deleted when it's dropped
+/// unless it was moved with [`TempFile::persist`].
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    size: u64,
+}
+
+impl TempFile {
+    /// Creates an empty file with a name no other TempFile uses.
+    fn create() -> io::Result<Self> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        loop {
+            let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let name = format!("blitzkrieg-{}-{id}.upload", std::process::id());
+            let path = std::env::temp_dir().join(name);
+            match fs::File::options().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path, size: 0 }),
+                // Left over by an earlier process with the same id.
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Moves the file to `path`, so it's kept.
+    pub fn persist(self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        // Renaming fails across file systems, where the file is copied instead.
+        if fs::rename(&self.path, path).is_err() {
+            fs::copy(&self.path, path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Parses a whole `multipart/form-data` body.
+pub(crate) fn parse(data: &[u8], boundary: &str) -> Result<FormdataBody, Error> {
+    let mut reader = MultipartReader::new(data, boundary);
+    let mut parts = vec![];
+    while let Some(part) = reader.next_part()? {
+        let mut content = vec![];
+        reader.read_to_end(&mut content)?;
+        parts.push(match part.file_name {
+            Some(file_name) => Formdata::FormdataFile(FormdataFile {
+                name: part.name,
+                file_name,
+                content,
+                content_type: part.content_type,
+                charset: part.charset,
+                headers: part.headers,
+            }),
+            None => Formdata::FormdataText(FormdataText {
+                name: part.name,
+                value: String::from_utf8_lossy(&content).into_owned(),
+                content_type: part.content_type,
+                charset: part.charset,
+                headers: part.headers,
+            }),
+        });
+    }
+    Ok(FormdataBody { parts })
+}
+
+/// Decodes an RFC 5987 extended parameter value like `UTF-8''na%C3%AFve.txt`.
+fn decode_extended(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes = percent_decode_bytes(parts.next()?, false);
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+/// Returns the position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
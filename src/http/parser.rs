@@ -1,88 +1,159 @@
-use super::{BodyType, File, FormdataBody, FormdataFile, FormdataText, HTTPMethod, Request};
+use super::{
+    chunked::ChunkedDecoder,
+    encoding, multipart,
+    url::{parse_query, percent_decode},
+    BodyType, Error, File, FormdataBody, FormdataText, HTTPMethod, HeaderMap, Limits, Request,
+    Version,
+};
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, Read},
+    io::{self, BufRead, BufReader, Read, Take},
     str::FromStr,
 };
 
 impl Request {
     /// Constructs an HTTP Request from a TCP Stream.
-    pub fn from_tcp_stream<T: Read>(reader: &mut BufReader<&mut T>) -> Result<Request, String> {
-        let mut request = String::new();
+    pub fn from_tcp_stream<T: Read>(reader: &mut BufReader<&mut T>) -> Result<Request, Error> {
+        let limits = Limits::default();
+        let head = Request::read_head(reader, &limits)?;
+        let (body, trailers) = Request::read_body(reader, &head, &limits)?;
+        let mut request = Request::parse(head, body)?;
+        request.trailers = trailers;
+        Ok(request)
+    }
+
+    /// Reads the request line and headers, up to and including the empty line.
+    pub(crate) fn read_head<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<String, Error> {
+        // Empty lines before the request line are ignored.
+        let mut request = vec![];
+        while request.is_empty() || request == b"\r\n" {
+            request = read_line_limited(reader, limits.max_request_line, Error::UriTooLong)?;
+            if request.is_empty() {
+                return Err(Error::ConnectionClosed);
+            }
+        }
+        let mut header_bytes = 0;
+        let mut header_count = 0;
         loop {
-            let r = reader.read_line(&mut request).map_err(|e| e.to_string())?;
-            if r < 3 {
+            let remaining = limits.max_header_bytes.saturating_sub(header_bytes);
+            let line = read_line_limited(reader, remaining, Error::HeadersTooLarge)?;
+            header_bytes += line.len();
+            request.extend_from_slice(&line);
+            if line.len() < 3 {
                 //detect empty line
                 break;
             }
+            header_count += 1;
+            if header_count > limits.max_headers {
+                return Err(Error::HeadersTooLarge);
+            }
         }
-        if request.len() == 0 {
-            return Err("Finished".into());
-        }
-        let mut size = 0;
-        let linesplit = request.split("\n");
-        for l in linesplit {
-            if l.starts_with("Content-Length") {
-                size = l
-                    .split(":")
-                    .skip(1)
-                    .next()
-                    .unwrap_or_default()
-                    .trim()
-                    .parse::<usize>()
-                    .map_err(|_| "Invalid Content-Length value")?;
+        String::from_utf8(request).map_err(|_| Error::BadHeader)
+    }
+
+    /// Reads the body declared by the request's headers, decoding it if it's chunked.
+    /// Returns the body and the trailers sent after a chunked body.
+    pub(crate) fn read_body<R: BufRead>(
+        reader: &mut R,
+        head: &str,
+        limits: &Limits,
+    ) -> Result<(Vec<u8>, HeaderMap), Error> {
+        match BodyReader::new(reader, head, limits)? {
+            BodyReader::Chunked(mut decoder) => {
+                let mut buffer = vec![];
+                decoder.read_to_end(&mut buffer)?;
+                Ok((buffer, decoder.into_trailers()))
+            }
+            BodyReader::Sized(mut body) => {
+                let size = body.limit() as usize;
+                // Checked before allocating, since the client controls the size.
+                if size > limits.max_body_size {
+                    return Err(Error::BodyTooLarge);
+                }
+                let mut buffer = vec![0; size]; //New Vector with size of Content
+                body.read_exact(&mut buffer)?; //Get the Body Content.
+                Ok((buffer, HeaderMap::new()))
             }
         }
-        let mut buffer = vec![0; size]; //New Vector with size of Content
-        reader.read_exact(&mut buffer).map_err(|e| e.to_string())?; //Get the Body Content.
-        let request = Request::parse(request, buffer);
-        request
     }
+
     /// Parses an HTTP Request from a String and its body from a vector of bytes.
-    pub fn parse(request: String, body: Vec<u8>) -> Result<Request, String> {
+    /// A body sent with a gzip or deflate `Content-Encoding` is decoded first.
+    pub fn parse(request: String, body: Vec<u8>) -> Result<Request, Error> {
+        let mut request = Request::parse_head(&request)?;
+        let max_size = Limits::default().max_body_size;
+        let body = encoding::decode(&mut request.headers, body, max_size)?;
+        request.body = Request::parse_body(&request.headers, body)?;
+        Ok(request)
+    }
+
+    /// Parses the request line and headers, leaving the body empty.
+    pub(crate) fn parse_head(request: &str) -> Result<Request, Error> {
         let request_lines: Vec<&str> = request.split("\r\n").collect();
         let mut first_line_iter = request_lines[0].split_whitespace();
-        let method = first_line_iter
-            .next()
-            .ok_or("Error while parsing HTTP method")?;
-        let uri = first_line_iter.next().ok_or("Error while parsing URI")?;
-        let mut headers: HashMap<String, String> = HashMap::new();
+        let method = first_line_iter.next().ok_or(Error::MalformedRequestLine)?;
+        let uri = first_line_iter.next().ok_or(Error::MalformedRequestLine)?;
+        let version = first_line_iter.next().ok_or(Error::MalformedRequestLine)?;
+        if first_line_iter.next().is_some() {
+            return Err(Error::MalformedRequestLine);
+        }
+        let mut headers = HeaderMap::new();
         for header in request_lines.iter().skip(1) {
-            if header.len() > 0 {
-                let split_index = header.find(": ").expect("Header doesn't have a ': '");
-                headers.insert(
-                    header[..split_index].to_string(),
-                    header[split_index + 2..].to_string(),
-                );
+            if !header.is_empty() {
+                let (name, value) = header.split_once(':').ok_or(Error::BadHeader)?;
+                if name.is_empty() || name.ends_with(char::is_whitespace) {
+                    return Err(Error::BadHeader);
+                }
+                headers.try_append(name, value.trim())?;
             }
         }
-        let default = String::new();
-        let content_type = headers.get("Content-Type").unwrap_or(&default);
-        if content_type.contains("multipart/form-data") {
-            // This is because the line will have extra chars like " multipart/form-data; boundary=X-INSOMNIA-BOUNDARY"
-            headers.insert(
-                "Content-Type".to_string(),
-                "multipart/form-data".to_string(),
-            );
-            let formdatabody = Request::parse_formdata(&body)?;
-            return Ok(Request {
-                path: uri.to_string(),
-                body: Some(BodyType::FormdataBody(formdatabody)),
-                method: HTTPMethod::from_str(method)?,
-                headers,
-            });
+        let method = HTTPMethod::from_str(method)?;
+        let version = Version::from_str(version)?;
+        let (raw_path, raw_query) = split_target(uri);
+        let path = percent_decode(&raw_path, false);
+        let query_params = raw_query.as_deref().map(parse_query).unwrap_or_default();
+        Ok(Request {
+            method,
+            version,
+            path,
+            raw_path,
+            raw_query,
+            query_params,
+            headers,
+            body: None,
+            params: HashMap::new(),
+            trailers: HeaderMap::new(),
+        })
+    }
+
+    /// Returns the boundary of a `multipart/form-data` request, or `None` for other requests.
+    pub(crate) fn multipart_boundary(&self) -> Result<Option<String>, Error> {
+        let content_type = self.headers.get("Content-Type").unwrap_or_default();
+        if !content_type.contains("multipart/form-data") {
+            return Ok(None);
+        }
+        multipart::boundary(content_type).map(Some)
+    }
+
+    /// Parses a body according to the request's Content-Type.
+    pub(crate) fn parse_body(
+        headers: &HeaderMap,
+        body: Vec<u8>,
+    ) -> Result<Option<BodyType>, Error> {
+        let content_type = headers.get("Content-Type").unwrap_or_default();
+        let body = if content_type.contains("multipart/form-data") {
+            let boundary = multipart::boundary(content_type)?;
+            let formdatabody = Request::parse_formdata(&body, &boundary)?;
+            Some(BodyType::FormdataBody(formdatabody))
+        } else if content_type.contains("application/x-www-form-urlencoded") {
+            let body = String::from_utf8(body).map_err(|_| Error::InvalidBody)?;
+            let fields = parse_query(&body)
+                .into_iter()
+                .map(|(name, value)| FormdataText::new(name, value))
+                .collect();
+            Some(BodyType::UrlEncoded(fields))
         } else if content_type.contains("application/json") || content_type.contains("text/xml") {
-            let body = std::str::from_utf8(body.as_slice()).map_err(|e| e.to_string())?;
-            let body = match body.len() {
-                0 => None,
-                _ => Some(BodyType::Text(body.to_string())),
-            };
-            return Ok(Request {
-                path: uri.to_string(),
-                body,
-                method: HTTPMethod::from_str(method)?,
-                headers,
-            });
+            parse_text(body)?
         }
         // files
         else if content_type.contains("application/")
@@ -91,111 +162,153 @@ impl Request {
             || content_type.contains("video/")
         {
             let extension = content_type
-                .split("/")
-                .last()
-                .ok_or("Content type for application wasn't specified")?;
-            return Ok(Request {
-                path: uri.to_string(),
-                body: Some(BodyType::File(File {
-                    extension: extension.to_string(),
-                    content: body,
-                })),
-                method: HTTPMethod::from_str(method)?,
-                headers,
-            });
+                .split('/')
+                .next_back()
+                .ok_or(Error::BadHeader)?;
+            Some(BodyType::File(File {
+                extension: extension.to_string(),
+                content: body,
+            }))
         } else {
-            let body = std::str::from_utf8(body.as_slice()).map_err(|e| e.to_string())?;
-            let body = match body.len() {
-                0 => None,
-                _ => Some(BodyType::Text(body.to_string())),
-            };
-            return Ok(Request {
-                path: uri.to_string(),
-                body,
-                method: HTTPMethod::from_str(method)?,
-                headers,
-            });
-        }
+            parse_text(body)?
+        };
+        Ok(body)
     }
 
-    /// Parses and returns a Formdata body.
-    pub fn parse_formdata(data: &Vec<u8>) -> Result<FormdataBody, String> {
-        // Get separator value
-        let n = data.len();
-        let mut i = 0;
-        let mut form_files: Vec<FormdataFile> = vec![];
-        let mut form_fields: Vec<FormdataText> = vec![];
-        while i < n - 1 {
-            let mut line = String::new();
-            while !(data[i] == 13 && data[i + 1] == 10) {
-                line.push(data[i] as char);
-                i += 1;
+    /// Parses and returns a Formdata body delimited by `boundary`.
+    pub fn parse_formdata(data: &[u8], boundary: &str) -> Result<FormdataBody, Error> {
+        multipart::parse(data, boundary)
+    }
+}
+
+/// A request body framed by its Content-Length or by the chunked transfer coding.
+pub(crate) enum BodyReader<R> {
+    Sized(Take<R>),
+    Chunked(ChunkedDecoder<R>),
+}
+
+impl<R: BufRead> BodyReader<R> {
+    /// Checks the framing headers of a request and prepares to read its body.
+    /// The body size isn't checked against the limits.
+    pub(crate) fn new(reader: R, head: &str, limits: &Limits) -> Result<Self, Error> {
+        let transfer_encoding: Vec<String> = head_values(head, "Transfer-Encoding")
+            .flat_map(|value| value.split(','))
+            .map(|coding| coding.trim().to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty())
+            .collect();
+        let content_length: Vec<&str> = head_values(head, "Content-Length").collect();
+        if !transfer_encoding.is_empty() {
+            // A message with both can be read differently by a proxy, which allows request smuggling.
+            if !content_length.is_empty() {
+                return Err(Error::InvalidContentLength);
             }
-            i += 4;
-            if line == "" || line.starts_with("-") {
-                i += 1;
-                continue;
+            if transfer_encoding != ["chunked"] {
+                return Err(Error::UnsupportedTransferEncoding);
             }
-            if line.contains("form-data") && line.contains("filename") {
-                let splitted = line.replace("\"", "");
-                let splitted: Vec<&str> = splitted.split("; ").collect();
-                let name = &splitted[1][5..];
-
-                let filename = &splitted[2][9..];
-                // Start parsing file
-                // Ignore \r\n
-                i += 4;
-                // Ignore content-type
-                while !(data[i] == 13 && data[i + 1] == 10) {
-                    i += 1;
-                }
-                // Ignore \r\n
-                i += 4;
-                let mut file: Vec<u8> = vec![];
-                while !(data[i] == 13
-                    && data[i + 1] == 10
-                    && data[i + 2] == 45
-                    && data[i + 3] == 45)
-                {
-                    file.push(data[i]);
-                    i += 1;
-                }
-
-                form_files.push(FormdataFile {
-                    name: name.into(),
-                    file_name: filename.into(),
-                    content: file,
-                })
-            } else if line.contains("form-data") {
-                let mut value = String::new();
-                // i += 4;
-                while !(data[i] == 13 && data[i + 1] == 10) {
-                    value.push(data[i] as char);
-                    i += 1;
-                }
-                let splitted = line.replace("\"", "");
-                let splitted: Vec<&str> = splitted.split("; ").collect();
-                let name = &splitted[1][5..];
-                form_fields.push(FormdataText {
-                    name: name.into(),
-                    value: value.into(),
-                })
-            } else {
-                // Shouldn't reach here if line doesn't start with "--"
-                if !line.contains("--") {
-                    return Err("Error while parsing form data".into());
-                }
+            return Ok(Self::Chunked(ChunkedDecoder::new(reader, limits)));
+        }
+        let mut size = None;
+        for value in content_length {
+            // `parse` accepts a leading `+`, which another parser could read differently.
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(Error::InvalidContentLength);
             }
-            i += 1;
+            let value = value
+                .parse::<u64>()
+                .map_err(|_| Error::InvalidContentLength)?;
+            if size.is_some_and(|size| size != value) {
+                return Err(Error::InvalidContentLength);
+            }
+            size = Some(value);
         }
-        let fields = match form_fields.len() {
-            0 => None,
-            _ => Some(form_fields),
-        };
-        let files = match form_files.len() {
-            0 => None,
-            _ => Some(form_files),
+        Ok(Self::Sized(reader.take(size.unwrap_or_default())))
+    }
+
+    /// Returns how much of a sized body is left to read, or `None` for a chunked body.
+    pub(crate) fn remaining(&self) -> Option<u64> {
+        match self {
+            Self::Sized(body) => Some(body.limit()),
+            Self::Chunked(_) => None,
+        }
+    }
+
+    /// Returns the underlying reader, positioned wherever reading the body stopped.
+    pub(crate) fn into_inner(self) -> R {
+        match self {
+            Self::Sized(body) => body.into_inner(),
+            Self::Chunked(decoder) => decoder.into_inner(),
+        }
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Sized(body) => body.read(buf),
+            Self::Chunked(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Parses a text body, which is `None` if it's empty.
+fn parse_text(body: Vec<u8>) -> Result<Option<BodyType>, Error> {
+    let body = String::from_utf8(body).map_err(|_| Error::InvalidBody)?;
+    match body.len() {
+        0 => Ok(None),
+        _ => Ok(Some(BodyType::Text(body))),
+    }
+}
+
+/// Splits a request-target into its raw path and its raw query, dropping any fragment.
+fn split_target(target: &str) -> (String, Option<String>) {
+    let target = target.split('#').next().unwrap_or_default();
+    // Absolute-form targets, as sent to proxies, start with a scheme and an authority.
+    let target = match target.split_once("://") {
+        Some((scheme, rest)) if !scheme.contains('/') => match rest.find(['/', '?']) {
+            Some(i) => &rest[i..],
+            None => "/",
+        },
+        _ => target,
+    };
+    match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    }
+}
+
+/// Returns the values of a header in a raw request head, matching its name case-insensitively.
+fn head_values<'a>(head: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    head.split("\r\n").skip(1).filter_map(move |line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Reads a line including its `\n`, failing with `error` once it's longer than `max` bytes.
+/// Returns an empty line at the end of the stream.
+pub(crate) fn read_line_limited<R: BufRead>(
+    reader: &mut R,
+    max: usize,
+    error: Error,
+) -> Result<Vec<u8>, Error> {
+    let mut line = vec![];
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(line);
+        }
+        let (chunk, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..=i], true),
+            None => (available, false),
         };
-        Ok(FormdataBody { fields, files })
+        if line.len() + chunk.len() > max {
+            return Err(error);
+        }
+        line.extend_from_slice(chunk);
+        let consumed = chunk.len();
+        reader.consume(consumed);
+        if done {
+            return Ok(line);
+        }
     }
 }
@@ -0,0 +1,272 @@
+use super::{conditional::if_range_matches, HTTPMethod, Request, Response, StatusCode};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// More ranges than this in one request are ignored and the whole representation is sent,
+/// since each range costs a seek and a part header.
+const MAX_RANGES: usize = 16;
+
+/// The ranges of a `Range` header, matched against the length of a representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteRanges {
+    /// The header is malformed or isn't about bytes, so it's ignored.
+    Ignored,
+    /// The satisfiable ranges, clamped to the representation, sorted,
+    /// and with overlapping or adjacent ranges merged so no byte is sent twice.
+    Satisfiable(Vec<Range<u64>>),
+    /// None of the ranges overlap the representation.
+    Unsatisfiable,
+}
+
+impl ByteRanges {
+    /// Parses a `Range` header like `bytes=0-99,200-,-50` for a representation of `length` bytes.
+    pub fn parse(value: &str, length: u64) -> ByteRanges {
+        let Some((unit, specs)) = value.split_once('=') else {
+            return ByteRanges::Ignored;
+        };
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return ByteRanges::Ignored;
+        }
+        let mut ranges = vec![];
+        let mut any = false;
+        for spec in specs
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+        {
+            let Some((start, end)) = spec.split_once('-') else {
+                return ByteRanges::Ignored;
+            };
+            let parse = |s: &str| match s.bytes().all(|b| b.is_ascii_digit()) {
+                true => s.parse::<u64>().ok(),
+                false => None,
+            };
+            any = true;
+            let range = match (start, end) {
+                // A suffix range, for the last bytes.
+                ("", suffix) => match parse(suffix) {
+                    Some(suffix) => length.saturating_sub(suffix)..length,
+                    None => return ByteRanges::Ignored,
+                },
+                (start, "") => match parse(start) {
+                    Some(start) => start..length,
+                    None => return ByteRanges::Ignored,
+                },
+                (start, end) => match (parse(start), parse(end)) {
+                    (Some(start), Some(end)) if start <= end => {
+                        start..end.saturating_add(1).min(length)
+                    }
+                    _ => return ByteRanges::Ignored,
+                },
+            };
+            if !range.is_empty() {
+                ranges.push(range);
+            }
+        }
+        match ranges.len() {
+            _ if !any => ByteRanges::Ignored,
+            0 => ByteRanges::Unsatisfiable,
+            n if n > MAX_RANGES => ByteRanges::Ignored,
+            _ => ByteRanges::Satisfiable(merge(ranges)),
+        }
+    }
+}
+
+/// Sorts ranges and merges the ones that overlap or touch.
+fn merge(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+impl Response {
+    /// Creates a response serving `length` bytes from `source`, honouring the request's
+    /// `Range` header. See [`Response::ranged_reader`].
+    pub fn ranged<R>(request: &Request, source: R, length: u64, content_type: &str) -> Self
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        Response::new(StatusCode::OK).ranged_reader(request, source, length, content_type)
+    }
+
+    /// Set reponse body from `length` bytes of `source`, honouring the request's `Range`
+    /// header. The status code is set to match.
+    ///
+    /// Without a usable `Range`, the whole source is sent with a 200. A single range gets a
+    /// 206 with `Content-Range`, several ranges a 206 with a `multipart/byteranges` body,
+    /// and ranges outside the source a 416. Only the requested bytes are read, and
+    /// `Accept-Ranges` tells clients that ranges are supported.
+    ///
+    /// Set the `ETag` or `Last-Modified` before, so an `If-Range` can be checked against
+    /// them: ranges of a representation that changed are never sent.
+    pub fn ranged_reader<R>(
+        mut self,
+        request: &Request,
+        mut source: R,
+        length: u64,
+        content_type: &str,
+    ) -> Self
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        // Range requests are only defined for GET.
+        let ranges = match (&request.method, request.headers.get("Range")) {
+            (HTTPMethod::GET, Some(value)) => match request.headers.get("If-Range") {
+                Some(if_range) if !if_range_matches(if_range, &self.headers) => ByteRanges::Ignored,
+                _ => ByteRanges::parse(value, length),
+            },
+            _ => ByteRanges::Ignored,
+        };
+        self.headers.insert("Accept-Ranges", "bytes");
+        let mut ranges = match ranges {
+            ByteRanges::Ignored => {
+                self.status_code = StatusCode::OK;
+                return self
+                    .header("Content-Type", content_type)
+                    .reader(source, Some(length));
+            }
+            ByteRanges::Unsatisfiable => {
+                self.status_code = StatusCode::RANGE_NOT_SATISFIABLE;
+                return self
+                    .header("Content-Range", &format!("bytes */{length}"))
+                    .body("Range Not Satisfiable");
+            }
+            ByteRanges::Satisfiable(ranges) => ranges,
+        };
+        self.status_code = StatusCode::PARTIAL_CONTENT;
+        if ranges.len() == 1 {
+            let range = ranges.remove(0);
+            if let Err(err) = source.seek(SeekFrom::Start(range.start)) {
+                println!("Error in seeking ranged body. {err}");
+                return Response::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("Internal Server Error");
+            }
+            let size = range.end - range.start;
+            return self
+                .header("Content-Type", content_type)
+                .header("Content-Range", &content_range(&range, length))
+                .reader(source.take(size), Some(size));
+        }
+        let body = ByteRangesBody::new(source, ranges, length, content_type);
+        let size = body.size();
+        let multipart_type = format!("multipart/byteranges; boundary={}", body.boundary);
+        self.header("Content-Type", &multipart_type)
+            .reader(body, Some(size))
+    }
+}
+
+/// Formats the `Content-Range` value of a range.
+fn content_range(range: &Range<u64>, length: u64) -> String {
+    format!("bytes {}-{}/{length}", range.start, range.end - 1)
+}
+
+/// Returns a boundary that's unlikely to appear in the content.
+fn boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("blitzkrieg-{nanos:08x}{count:08x}")
+}
+
+/// A piece of a `multipart/byteranges` body.
+enum Segment {
+    /// A delimiter and part headers.
+    Text(Vec<u8>),
+    /// Bytes of the source.
+    Data(Range<u64>),
+}
+
+/// Reads a `multipart/byteranges` body, with a part for each range of the source.
+struct ByteRangesBody<R> {
+    source: R,
+    segments: VecDeque<Segment>,
+    boundary: String,
+    /// Whether the source is positioned at the start of the current data segment.
+    positioned: bool,
+}
+
+impl<R: Read + Seek> ByteRangesBody<R> {
+    fn new(source: R, ranges: Vec<Range<u64>>, length: u64, content_type: &str) -> Self {
+        let boundary = boundary();
+        let mut segments = VecDeque::new();
+        for (i, range) in ranges.into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "\r\n" };
+            let header = format!(
+                "{separator}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+                content_range(&range, length)
+            );
+            segments.push_back(Segment::Text(header.into_bytes()));
+            segments.push_back(Segment::Data(range));
+        }
+        segments.push_back(Segment::Text(
+            format!("\r\n--{boundary}--\r\n").into_bytes(),
+        ));
+        Self {
+            source,
+            segments,
+            boundary,
+            positioned: false,
+        }
+    }
+
+    /// Returns the size of the whole body.
+    fn size(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.len() as u64,
+                Segment::Data(range) => range.end - range.start,
+            })
+            .sum()
+    }
+}
+
+impl<R: Read + Seek> Read for ByteRangesBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(segment) = self.segments.front_mut() else {
+                return Ok(0);
+            };
+            match segment {
+                Segment::Text(text) if text.is_empty() => {}
+                Segment::Text(text) => {
+                    let n = buf.len().min(text.len());
+                    buf[..n].copy_from_slice(&text[..n]);
+                    text.drain(..n);
+                    return Ok(n);
+                }
+                Segment::Data(range) if range.is_empty() => {}
+                Segment::Data(range) => {
+                    if !self.positioned {
+                        self.source.seek(SeekFrom::Start(range.start))?;
+                        self.positioned = true;
+                    }
+                    let remaining = usize::try_from(range.end - range.start).unwrap_or(usize::MAX);
+                    let max = buf.len().min(remaining);
+                    let n = self.source.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    range.start += n as u64;
+                    return Ok(n);
+                }
+            }
+            self.segments.pop_front();
+            self.positioned = false;
+        }
+    }
+}
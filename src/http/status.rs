@@ -0,0 +1,161 @@
+use std::fmt;
+
+/// HTTP response status code.
+///
+/// Codes are three digits between 100 and 599. Every code registered with IANA has a
+/// named constant and a canonical reason phrase, which is sent on the status line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StatusCode(u16);
+
+/// Defines a constant and a reason phrase for each registered status code.
+macro_rules! status_codes {
+    ($($code:literal $name:ident $reason:literal;)+) => {
+        impl StatusCode {
+            $(
+                #[doc = concat!("`", $code, " ", $reason, "`")]
+                pub const $name: StatusCode = StatusCode($code);
+            )+
+
+            /// Returns the reason phrase of a registered status code.
+            pub fn canonical_reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $($code => Some($reason),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    100 CONTINUE "Continue";
+    101 SWITCHING_PROTOCOLS "Switching Protocols";
+    102 PROCESSING "Processing";
+    103 EARLY_HINTS "Early Hints";
+    200 OK "OK";
+    201 CREATED "Created";
+    202 ACCEPTED "Accepted";
+    203 NON_AUTHORITATIVE_INFORMATION "Non-Authoritative Information";
+    204 NO_CONTENT "No Content";
+    205 RESET_CONTENT "Reset Content";
+    206 PARTIAL_CONTENT "Partial Content";
+    207 MULTI_STATUS "Multi-Status";
+    208 ALREADY_REPORTED "Already Reported";
+    226 IM_USED "IM Used";
+    300 MULTIPLE_CHOICES "Multiple Choices";
+    301 MOVED_PERMANENTLY "Moved Permanently";
+    302 FOUND "Found";
+    303 SEE_OTHER "See Other";
+    304 NOT_MODIFIED "Not Modified";
+    305 USE_PROXY "Use Proxy";
+    307 TEMPORARY_REDIRECT "Temporary Redirect";
+    308 PERMANENT_REDIRECT "Permanent Redirect";
+    400 BAD_REQUEST "Bad Request";
+    401 UNAUTHORIZED "Unauthorized";
+    402 PAYMENT_REQUIRED "Payment Required";
+    403 FORBIDDEN "Forbidden";
+    404 NOT_FOUND "Not Found";
+    405 METHOD_NOT_ALLOWED "Method Not Allowed";
+    406 NOT_ACCEPTABLE "Not Acceptable";
+    407 PROXY_AUTHENTICATION_REQUIRED "Proxy Authentication Required";
+    408 REQUEST_TIMEOUT "Request Timeout";
+    409 CONFLICT "Conflict";
+    410 GONE "Gone";
+    411 LENGTH_REQUIRED "Length Required";
+    412 PRECONDITION_FAILED "Precondition Failed";
+    413 CONTENT_TOO_LARGE "Content Too Large";
+    414 URI_TOO_LONG "URI Too Long";
+    415 UNSUPPORTED_MEDIA_TYPE "Unsupported Media Type";
+    416 RANGE_NOT_SATISFIABLE "Range Not Satisfiable";
+    417 EXPECTATION_FAILED "Expectation Failed";
+    421 MISDIRECTED_REQUEST "Misdirected Request";
+    422 UNPROCESSABLE_CONTENT "Unprocessable Content";
+    423 LOCKED "Locked";
+    424 FAILED_DEPENDENCY "Failed Dependency";
+    425 TOO_EARLY "Too Early";
+    426 UPGRADE_REQUIRED "Upgrade Required";
+    428 PRECONDITION_REQUIRED "Precondition Required";
+    429 TOO_MANY_REQUESTS "Too Many Requests";
+    431 REQUEST_HEADER_FIELDS_TOO_LARGE "Request Header Fields Too Large";
+    451 UNAVAILABLE_FOR_LEGAL_REASONS "Unavailable For Legal Reasons";
+    500 INTERNAL_SERVER_ERROR "Internal Server Error";
+    501 NOT_IMPLEMENTED "Not Implemented";
+    502 BAD_GATEWAY "Bad Gateway";
+    503 SERVICE_UNAVAILABLE "Service Unavailable";
+    504 GATEWAY_TIMEOUT "Gateway Timeout";
+    505 HTTP_VERSION_NOT_SUPPORTED "HTTP Version Not Supported";
+    506 VARIANT_ALSO_NEGOTIATES "Variant Also Negotiates";
+    507 INSUFFICIENT_STORAGE "Insufficient Storage";
+    508 LOOP_DETECTED "Loop Detected";
+    510 NOT_EXTENDED "Not Extended";
+    511 NETWORK_AUTHENTICATION_REQUIRED "Network Authentication Required";
+}
+
+impl StatusCode {
+    /// Creates a status code, or returns `None` if it isn't between 100 and 599.
+    /// Unregistered codes are allowed and have no canonical reason phrase.
+    pub fn from_u16(code: u16) -> Option<StatusCode> {
+        match code {
+            100..=599 => Some(StatusCode(code)),
+            _ => None,
+        }
+    }
+
+    /// Returns the code as a number.
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns whether the code is 1xx.
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    /// Returns whether the code is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    /// Returns whether the code is 3xx.
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    /// Returns whether the code is 4xx.
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    /// Returns whether the code is 5xx.
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        Self::OK
+    }
+}
+
+impl fmt::Display for StatusCode {
+    /// Writes the code followed by its reason phrase, like `404 Not Found`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {reason}", self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+impl PartialEq<u16> for StatusCode {
+    fn eq(&self, other: &u16) -> bool {
+        self.0 == *other
+    }
+}
@@ -0,0 +1,59 @@
+/// Decodes `%XX` escapes in a URL component.
+///
+/// Invalid escapes are kept as they are and invalid UTF-8 is replaced.
+/// With `plus_as_space`, as used by query strings and forms, `+` is decoded to a space.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> String {
+    String::from_utf8_lossy(&percent_decode_bytes(input, plus_as_space)).into_owned()
+}
+
+/// Decodes `%XX` escapes in a URL component into raw bytes.
+pub(crate) fn percent_decode_bytes(input: &str, plus_as_space: bool) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let escape = bytes.get(i + 1..i + 3).and_then(|hex| {
+                    let hex = std::str::from_utf8(hex).ok()?;
+                    u8::from_str_radix(hex, 16).ok()
+                });
+                match escape {
+                    // from_str_radix accepts a sign, which isn't a valid escape.
+                    Some(byte) if bytes[i + 1].is_ascii_hexdigit() => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    decoded
+}
+
+/// Splits a raw request path into its decoded segments, so an encoded `/` stays part
+/// of its segment. Empty segments are skipped.
+pub(crate) fn path_segments(raw_path: &str) -> impl Iterator<Item = String> + '_ {
+    raw_path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|segment| percent_decode(segment, false))
+}
+
+/// Parses an `application/x-www-form-urlencoded` string, like a query string,
+/// into decoded name/value pairs in their original order.
+pub fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name, true), percent_decode(value, true))
+        })
+        .collect()
+}
@@ -6,9 +6,23 @@
 #![doc(html_logo_url = "https://cdn-icons-png.flaticon.com/512/3857/3857541.png")]
 /// A module for parsing HTTP.
 pub mod http;
+/// A module for wrapping handlers with middlewares.
+pub mod middleware;
+/// A module for routing requests to handlers by method and path.
+pub mod router;
 /// A module for implementing a Server struct.
 pub mod server;
+/// A module for gracefully shutting down the server.
+pub mod shutdown;
+/// A module for serving static files from a directory.
+pub mod static_files;
 /// A module for implementing a threadpool for the server.
 pub mod threadpool;
 
+// The tests are kept as they were first written, before clippy was part of the build.
+#[allow(
+    clippy::assertions_on_constants,
+    clippy::useless_conversion,
+    clippy::vec_init_then_push
+)]
 mod test;
@@ -0,0 +1,410 @@
+#[cfg(feature = "compression")]
+use crate::http::{self, Body, ContentCoding, HTTPMethod};
+use crate::http::{Request, Response, StatusCode};
+#[cfg(feature = "compression")]
+use std::io;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A layer wrapping the server's handler.
+///
+/// A middleware receives the request and the rest of the chain as [`Next`].
+/// It can inspect or modify the request before calling [`Next::run`],
+/// short-circuit by returning its own response without calling it,
+/// or post-process the response returned downstream.
+///
+/// Closures with the signature `Fn(Request, Next) -> Response` are middlewares too.
+pub trait Middleware: Send + Sync + 'static {
+    /// Handles a request, optionally passing it down the chain.
+    fn handle(&self, request: Request, next: Next) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, Next) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request, next: Next) -> Response {
+        self(request, next)
+    }
+}
+
+/// The remaining part of a middleware chain.
+pub struct Next<'a> {
+    handler: &'a (dyn Fn(Request) -> Response + Send + Sync),
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(handler: &'a (dyn Fn(Request) -> Response + Send + Sync)) -> Self {
+        Self { handler }
+    }
+
+    /// Passes the request to the next middleware, or to the handler if this is the last layer.
+    pub fn run(self, request: Request) -> Response {
+        (self.handler)(request)
+    }
+}
+
+/// Wraps a handler with middlewares.
+/// The first middleware is the outermost layer, so it sees the request first and the response last.
+pub(crate) fn compose(
+    handler: Box<dyn Fn(Request) -> Response + Send + Sync>,
+    middlewares: Vec<Box<dyn Middleware>>,
+) -> Box<dyn Fn(Request) -> Response + Send + Sync> {
+    middlewares
+        .into_iter()
+        .rev()
+        .fold(handler, |inner, middleware| {
+            Box::new(move |request| middleware.handle(request, Next::new(&inner)))
+        })
+}
+
+/// Middleware that tags every request and response with an `X-Request-Id` header.
+///
+/// An id sent by the client is kept, otherwise a new one is generated.
+pub struct RequestId {
+    prefix: String,
+    counter: AtomicU64,
+}
+
+impl RequestId {
+    pub const HEADER: &'static str = "X-Request-Id";
+
+    /// Creates a RequestId middleware.
+    pub fn new() -> Self {
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self {
+            prefix: format!("{:x}", start as u64),
+            counter: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for RequestId {
+    fn handle(&self, mut request: Request, next: Next) -> Response {
+        let id = match request.headers.get(Self::HEADER) {
+            Some(id) => id.to_string(),
+            None => {
+                let n = self.counter.fetch_add(1, Ordering::Relaxed);
+                let id = format!("{}-{n}", self.prefix);
+                request.headers.insert(Self::HEADER, id.clone());
+                id
+            }
+        };
+        let mut response = next.run(request);
+        response.headers.insert(Self::HEADER, id);
+        response
+    }
+}
+
+/// Middleware that adds an `X-Response-Time` header with the time spent in the handler.
+#[derive(Default)]
+pub struct Timing;
+
+impl Timing {
+    pub const HEADER: &'static str = "X-Response-Time";
+}
+
+impl Middleware for Timing {
+    fn handle(&self, request: Request, next: Next) -> Response {
+        let start = Instant::now();
+        let mut response = next.run(request);
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        response
+            .headers
+            .insert(Self::HEADER, format!("{elapsed:.3}ms"));
+        response
+    }
+}
+
+type Verifier = Box<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// Middleware implementing HTTP Basic authentication.
+///
+/// Requests without valid credentials get a 401 response with a `WWW-Authenticate` header.
+pub struct BasicAuth {
+    /// The `WWW-Authenticate` value, with the realm quoted.
+    challenge: String,
+    verify: Verifier,
+}
+
+impl BasicAuth {
+    /// Creates a BasicAuth middleware that checks credentials with the `verify` function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the realm contains control characters, which can't be sent in a header.
+    pub fn new<F>(realm: &str, verify: F) -> Self
+    where
+        F: Fn(&str, &str) -> bool + Send + Sync + 'static,
+    {
+        assert!(
+            !realm.chars().any(|c| c.is_ascii_control() && c != '\t'),
+            "Invalid realm {realm:?}"
+        );
+        // The realm is a quoted-string, where quotes and backslashes are escaped.
+        let realm = realm.replace('\\', "\\\\").replace('"', "\\\"");
+        Self {
+            challenge: format!("Basic realm=\"{realm}\", charset=\"UTF-8\""),
+            verify: Box::new(verify),
+        }
+    }
+
+    /// Creates a BasicAuth middleware that accepts a single user.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the realm contains control characters, like [`BasicAuth::new`].
+    pub fn single(realm: &str, username: &str, password: &str) -> Self {
+        let (username, password) = (username.to_string(), password.to_string());
+        Self::new(realm, move |u, p| u == username && p == password)
+    }
+
+    /// Extracts the username and password from an `Authorization` header value.
+    fn credentials(header: &str) -> Option<(String, String)> {
+        let (scheme, encoded) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = String::from_utf8(decode_base64(encoded.trim())?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        Some((username.to_string(), password.to_string()))
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn handle(&self, request: Request, next: Next) -> Response {
+        let authorized = request
+            .headers
+            .get("Authorization")
+            .and_then(Self::credentials)
+            .is_some_and(|(username, password)| (self.verify)(&username, &password));
+        if authorized {
+            return next.run(request);
+        }
+        Response::new(StatusCode::UNAUTHORIZED)
+            .header("WWW-Authenticate", &self.challenge)
+            .body("Unauthorized")
+    }
+}
+
+/// Middleware that compresses response bodies with gzip or deflate, as negotiated
+/// with the request's `Accept-Encoding`. Requires the `compression` feature.
+///
+/// Only successful responses with a textual `Content-Type`, like HTML, CSS, JavaScript,
+/// JSON or XML, are compressed, and only if their body isn't smaller than the minimum
+/// size. Streamed bodies of unknown length are always compressed. Responses that
+/// already have a `Content-Encoding`, partial responses and responses with
+/// `Cache-Control: no-transform` are left alone.
+///
+/// Compressible responses get `Vary: Accept-Encoding` whether they're compressed or
+/// not, and the `ETag` of a compressed response gets the coding as a suffix, since
+/// its bytes differ. The suffix is taken off the tags of `If-Match`, `If-None-Match`
+/// and `If-Range` before the handler sees them, so they're compared with its own tag.
+/// A compressed response loses `Accept-Ranges`. A HEAD response gets the headers a
+/// GET would, but a streamed body isn't compressed, since it wouldn't be sent.
+#[cfg(feature = "compression")]
+pub struct Compression {
+    min_size: u64,
+    level: u32,
+}
+
+#[cfg(feature = "compression")]
+impl Compression {
+    /// Creates a Compression middleware for bodies of at least 1 KiB, at level 6.
+    pub fn new() -> Self {
+        Self {
+            min_size: 1024,
+            level: 6,
+        }
+    }
+
+    /// Sets the size below which bodies aren't compressed,
+    /// since the headers of small bodies outweigh what compression saves.
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Sets the compression level, from 0 for none to 9 for the smallest output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the level is above 9.
+    pub fn level(mut self, level: u32) -> Self {
+        assert!(level <= 9, "Invalid compression level {level}");
+        self.level = level;
+        self
+    }
+
+    /// Returns whether a response's content is worth compressing.
+    fn is_compressible(response: &Response) -> bool {
+        let Some(content_type) = response.headers.get("Content-Type") else {
+            return false;
+        };
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let textual = media_type.starts_with("text/")
+            || media_type.ends_with("+json")
+            || media_type.ends_with("+xml")
+            || matches!(
+                media_type.as_str(),
+                "application/json"
+                    | "application/javascript"
+                    | "application/xml"
+                    | "application/wasm"
+                    | "image/svg+xml"
+            );
+        let no_transform = response
+            .headers
+            .get_all("Cache-Control")
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+        textual
+            && !no_transform
+            && response.status_code.is_success()
+            && response.status_code != StatusCode::PARTIAL_CONTENT
+            && !response.headers.contains_key("Content-Encoding")
+            && !response.headers.contains_key("Content-Range")
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Middleware for Compression {
+    fn handle(&self, mut request: Request, next: Next) -> Response {
+        let accept_encoding: Vec<&str> = request.headers.get_all("Accept-Encoding").collect();
+        let coding = ContentCoding::negotiate(&accept_encoding.join(","));
+        let head = request.method == HTTPMethod::HEAD;
+        let tagged = coding.is_some_and(|coding| strip_etag_suffixes(&mut request, coding));
+        let mut response = next.run(request);
+        if response.status_code == StatusCode::NOT_MODIFIED && tagged {
+            // The client's copy is the compressed one, so it keeps the compressed tag.
+            add_vary(&mut response);
+            add_etag_suffix(&mut response, coding.unwrap());
+            return response;
+        }
+        if !Self::is_compressible(&response) {
+            return response;
+        }
+        add_vary(&mut response);
+        let Some(coding) = coding else {
+            return response;
+        };
+        if response
+            .body
+            .length()
+            .is_some_and(|length| length == 0 || length < self.min_size)
+        {
+            return response;
+        }
+        let body = std::mem::take(&mut response.body);
+        response.body = match body {
+            // A GET would get a streamed body of unknown length, so a HEAD gets none.
+            Body::Reader { .. } | Body::Chunks(_) if head => Body::from_reader(io::empty(), None),
+            // Bodies in memory are compressed anyway, so the length is the one a GET gets.
+            body => match http::encode(body, coding, self.level) {
+                Ok(body) => body,
+                Err(err) => {
+                    println!("Error in compressing response body. {err}");
+                    return Response::new(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body("Internal Server Error");
+                }
+            },
+        };
+        response.headers.insert("Content-Encoding", coding.as_str());
+        // Ranges would be of the compressed bytes, which change with the compression.
+        response.headers.remove("Accept-Ranges");
+        add_etag_suffix(&mut response, coding);
+        response
+    }
+}
+
+#[cfg(feature = "compression")]
+fn add_vary(response: &mut Response) {
+    let varies = response
+        .headers
+        .get_all("Vary")
+        .flat_map(|value| value.split(','))
+        .any(|name| name.trim() == "*" || name.trim().eq_ignore_ascii_case("Accept-Encoding"));
+    if !varies {
+        response.headers.append("Vary", "Accept-Encoding");
+    }
+}
+
+/// Suffixes the `ETag` of a response with a coding, like `"v1"` to `"v1-gzip"`.
+#[cfg(feature = "compression")]
+fn add_etag_suffix(response: &mut Response, coding: ContentCoding) {
+    if let Some(etag) = response.headers.get("ETag") {
+        if let Some(tag) = etag.strip_suffix('"') {
+            let etag = format!("{tag}-{}\"", coding.as_str());
+            response.headers.insert("ETag", etag);
+        }
+    }
+}
+
+/// Takes the suffix of a coding off the entity tags in a request's conditional headers.
+/// Returns whether there was any.
+#[cfg(feature = "compression")]
+fn strip_etag_suffixes(request: &mut Request, coding: ContentCoding) -> bool {
+    let suffix = format!("-{}\"", coding.as_str());
+    let mut stripped = false;
+    for name in ["If-Match", "If-None-Match", "If-Range"] {
+        let values: Vec<String> = request.headers.get_all(name).map(Into::into).collect();
+        if !values.iter().any(|value| value.contains(&suffix)) {
+            continue;
+        }
+        stripped = true;
+        request.headers.remove(name);
+        for value in values {
+            request.headers.append(name, value.replace(&suffix, "\""));
+        }
+    }
+    stripped
+}
+
+/// Decodes standard base64 with optional padding.
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(output)
+}
@@ -0,0 +1,254 @@
+use crate::http::{path_segments, HTTPMethod, Request, Response, StatusCode};
+use std::collections::HashMap;
+
+type Handler = Box<dyn Fn(Request) -> Response + Send + Sync>;
+
+/// A single segment of a route pattern.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    /// A literal segment, e.g. `users` in `/users/:id`.
+    Static(String),
+    /// A named parameter, e.g. `:id` in `/users/:id`.
+    Param(String),
+    /// A catch-all parameter matching the rest of the path, e.g. `*rest` in `/static/*rest`.
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Lower ranks are more specific and win when several routes match a path.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Static(_) => 0,
+            Self::Param(_) => 1,
+            Self::Wildcard(_) => 2,
+        }
+    }
+}
+
+/// A matched route and the parameters extracted from the path.
+type RouteMatch<'a> = (&'a Route, HashMap<String, String>);
+
+struct Route {
+    method: HTTPMethod,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+impl Route {
+    /// Matches a raw request path against the route pattern, decoding each segment.
+    /// Returns the extracted path parameters on success.
+    fn matches(&self, raw_path: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<String> = path_segments(raw_path).collect();
+        let mut params = HashMap::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(value) => {
+                    if parts.get(i) != Some(value) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), parts.get(i)?.clone());
+                }
+                Segment::Wildcard(name) => {
+                    let rest = parts.get(i..).unwrap_or_default().join("/");
+                    params.insert(name.clone(), rest);
+                    return Some(params);
+                }
+            }
+        }
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        Some(params)
+    }
+}
+
+/// Parses a route pattern like `/users/:id` or `/static/*rest` into segments.
+///
+/// # Panics
+///
+/// Panics if a parameter has no name or if a wildcard is not the last segment.
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let parts: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let mut segments = Vec::with_capacity(parts.len());
+    for (i, part) in parts.iter().enumerate() {
+        if let Some(name) = part.strip_prefix(':') {
+            assert!(!name.is_empty(), "Route parameter in {pattern} has no name");
+            segments.push(Segment::Param(name.to_string()));
+        } else if let Some(name) = part.strip_prefix('*') {
+            assert!(!name.is_empty(), "Route wildcard in {pattern} has no name");
+            assert!(
+                i == parts.len() - 1,
+                "Route wildcard must be the last segment in {pattern}"
+            );
+            segments.push(Segment::Wildcard(name.to_string()));
+        } else {
+            segments.push(Segment::Static(part.to_string()));
+        }
+    }
+    segments
+}
+
+/// HTTP Router struct.
+///
+/// Dispatches requests to handlers registered per HTTP method and path pattern.
+/// Patterns may contain named parameters (`/users/:id`) and a trailing
+/// wildcard (`/static/*rest`), which are available through [`Request::param`].
+///
+/// When several routes match, literal segments win over parameters and
+/// parameters win over wildcards.
+/// If no route matches the path, a 404 response is returned.
+/// If the path matches but the method doesn't, a 405 response with an `Allow` header is returned,
+/// which lists HEAD along with GET.
+/// Extension methods that no route handles get a 501 response.
+/// HEAD requests are handled by the GET route when there's no HEAD route for the path.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    /// Creates an empty Router.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for a method and a path pattern.
+    pub fn route<F>(mut self, method: HTTPMethod, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method,
+            segments: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Registers a handler for GET requests.
+    pub fn get<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::GET, pattern, handler)
+    }
+
+    /// Registers a handler for POST requests.
+    pub fn post<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::POST, pattern, handler)
+    }
+
+    /// Registers a handler for PUT requests.
+    pub fn put<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::PUT, pattern, handler)
+    }
+
+    /// Registers a handler for PATCH requests.
+    pub fn patch<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::PATCH, pattern, handler)
+    }
+
+    /// Registers a handler for DELETE requests.
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::DELETE, pattern, handler)
+    }
+
+    /// Registers a handler for HEAD requests.
+    pub fn head<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::HEAD, pattern, handler)
+    }
+
+    /// Registers a handler for OPTIONS requests.
+    pub fn options<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(HTTPMethod::OPTIONS, pattern, handler)
+    }
+
+    /// Finds the most specific route for a method and a path.
+    /// Also returns the other methods that have a route for the path.
+    fn find(&self, method: &HTTPMethod, path: &str) -> (Option<RouteMatch<'_>>, Vec<&HTTPMethod>) {
+        let mut best: Option<RouteMatch> = None;
+        let mut allowed: Vec<&HTTPMethod> = vec![];
+        for route in &self.routes {
+            let Some(params) = route.matches(path) else {
+                continue;
+            };
+            if route.method != *method {
+                if !allowed.contains(&&route.method) {
+                    allowed.push(&route.method);
+                }
+                continue;
+            }
+            let is_better = match &best {
+                Some((current, _)) => {
+                    let ranks = route.segments.iter().map(Segment::rank);
+                    ranks.lt(current.segments.iter().map(Segment::rank))
+                }
+                None => true,
+            };
+            if is_better {
+                best = Some((route, params));
+            }
+        }
+        (best, allowed)
+    }
+
+    /// Dispatches a request to the most specific matching route.
+    pub fn handle(&self, mut request: Request) -> Response {
+        let (mut best, allowed) = self.find(&request.method, &request.raw_path);
+        if best.is_none() && request.method == HTTPMethod::HEAD {
+            // Without a HEAD route, the GET one answers and the server drops the body.
+            best = self.find(&HTTPMethod::GET, &request.raw_path).0;
+        }
+        if let Some((route, params)) = best {
+            request.params = params;
+            return (route.handler)(request);
+        }
+        let implemented = !matches!(request.method, HTTPMethod::Extension(_))
+            || self
+                .routes
+                .iter()
+                .any(|route| route.method == request.method);
+        if !implemented {
+            return Response::new(StatusCode::NOT_IMPLEMENTED).body("Not Implemented");
+        }
+        if allowed.is_empty() {
+            return Response::new(StatusCode::NOT_FOUND).body("Not Found");
+        }
+        let mut allow: Vec<String> = allowed.iter().map(|method| method.to_string()).collect();
+        // GET routes answer HEAD requests too.
+        if let Some(get) = allow.iter().position(|method| method == "GET") {
+            if !allow.iter().any(|method| method == "HEAD") {
+                allow.insert(get + 1, "HEAD".to_string());
+            }
+        }
+        let allow = allow.join(", ");
+        Response::new(StatusCode::METHOD_NOT_ALLOWED)
+            .header("Allow", &allow)
+            .body("Method Not Allowed")
+    }
+
+    /// Turns the Router into a handler that can be passed to [`crate::server::Server::new`].
+    pub fn into_handler(self) -> Box<dyn Fn(Request) -> Response + Send + Sync> {
+        Box::new(move |request| self.handle(request))
+    }
+}
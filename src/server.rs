@@ -1,98 +1,525 @@
-use crate::{http::Request, http::Response, threadpool::ThreadPool};
+use crate::{
+    http::{
+        self, BodyReader, BodyType, Error, HTTPMethod, Limits, Multipart, Request, Response,
+        StatusCode, Version, ACCEPTED_CODINGS,
+    },
+    middleware::{self, Middleware},
+    shutdown::ShutdownHandle,
+    threadpool::ThreadPool,
+};
 use std::{
-    io::{prelude::*, BufReader},
-    net::TcpListener,
-    sync::Arc,
+    any::Any,
+    io::{self, prelude::*, BufReader, BufWriter, ErrorKind},
+    net::{TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
 
+type Handler = Box<dyn Fn(Request) -> Response + Send + Sync>;
+type PanicHook = Box<dyn Fn(&HandlerPanic) + Send + Sync>;
+
+/// Details about a handler that panicked while handling a request.
+#[derive(Debug)]
+pub struct HandlerPanic {
+    /// The method of the request being handled.
+    pub method: HTTPMethod,
+    /// The path of the request being handled.
+    pub path: String,
+    /// The panic message, if the payload was a string.
+    pub message: String,
+}
+
+/// Server configuration.
+///
+/// A timeout of `None` means waiting forever.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How long a client has to send the request line and headers, from their first byte.
+    pub header_read_timeout: Option<Duration>,
+    /// How long a client has to send the request body, from the end of the headers.
+    /// For a streamed multipart body, how long each read waits for data instead.
+    pub body_read_timeout: Option<Duration>,
+    /// How long a single write of the response may block.
+    pub write_timeout: Option<Duration>,
+    /// How long an idle keep-alive connection is kept open while waiting for the next request.
+    pub keep_alive_timeout: Option<Duration>,
+    /// Size limits enforced while reading requests.
+    pub limits: Limits,
+    /// Whether `multipart/form-data` bodies are handed to the handler as a
+    /// [`BodyType::Multipart`] that reads the connection as parts are consumed,
+    /// instead of being read into memory first.
+    ///
+    /// `limits.max_body_size` only applies to the decoded size of a compressed
+    /// streamed body, so handlers should cap the size of each part they keep.
+    /// Whatever the handler leaves unread is discarded after the response is sent,
+    /// up to `limits.max_body_size`. If more is left, the connection is closed
+    /// instead. Defaults to `false`.
+    pub stream_multipart: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            header_read_timeout: Some(Duration::from_secs(10)),
+            body_read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            limits: Limits::default(),
+            stream_multipart: false,
+        }
+    }
+}
+
+/// State shared by every connection.
+struct Context {
+    /// The function that handles HTTP requests, wrapped with the middlewares.
+    handler: Handler,
+    /// The function that reports handler panics.
+    panic_hook: Option<PanicHook>,
+    /// Handle for shutting the server down.
+    shutdown: ShutdownHandle,
+    /// The server's configuration.
+    config: ServerConfig,
+}
+
 /// HTTP Server struct.
 pub struct Server {
     /// The server's internal threadpool.
     threadpool: ThreadPool,
     /// The server's TCP listener.
     listener: TcpListener,
-    /// The function that handles HTTP requests.
-    handler: Arc<Box<dyn Fn(Request) -> Response + Send + Sync + 'static>>,
+    /// State shared with the connections.
+    context: Arc<Context>,
+    /// How long to wait for in-flight requests when shutting down.
+    shutdown_timeout: Duration,
 }
 
-impl Server {
-    /// Creates a new HTTP Server.
-    pub fn new(
-        port: &str,
-        threads: usize,
-        handler: Box<dyn Fn(Request) -> Response + Send + Sync>,
-    ) -> Server {
-        let listener = TcpListener::bind(port).unwrap();
-        let pool = ThreadPool::new(threads);
+/// Builder for an HTTP Server.
+pub struct ServerBuilder {
+    address: String,
+    threads: usize,
+    handler: Option<Handler>,
+    middlewares: Vec<Box<dyn Middleware>>,
+    panic_hook: Option<PanicHook>,
+    shutdown_timeout: Duration,
+    config: ServerConfig,
+}
+
+impl ServerBuilder {
+    /// Sets the address the server listens on. Defaults to `127.0.0.1:3000`.
+    pub fn address(mut self, address: &str) -> Self {
+        self.address = address.to_string();
+        self
+    }
+
+    /// Sets the number of threads in the server's threadpool. Defaults to 4.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Sets the function that handles HTTP requests.
+    /// Without a handler, every request gets a 404 response.
+    ///
+    /// HEAD requests reach the handler as they are: the server only drops the body of
+    /// the response, so a handler can answer them like a GET. Falling back to the GET
+    /// handler is up to the handler, as [`crate::router::Router`] does.
+    pub fn handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Wraps the handler with a middleware.
+    /// Middlewares run in the order they are added: the first one sees the request first
+    /// and the response last.
+    pub fn wrap<M: Middleware>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Sets the server's configuration.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets a function that's called when a handler panics.
+    /// The client gets a 500 response either way and the worker keeps serving requests.
+    pub fn on_panic<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HandlerPanic) + Send + Sync + 'static,
+    {
+        self.panic_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets how long a shutdown waits for in-flight requests before giving up on them.
+    /// Defaults to 30 seconds.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Binds the listener and creates the HTTP Server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the address can't be bound or if the number of threads is zero.
+    pub fn build(self) -> Server {
+        let listener = TcpListener::bind(&self.address).unwrap();
+        let pool = ThreadPool::new(self.threads);
+        let handler = self.handler.unwrap_or_else(|| {
+            Box::new(|_| Response::new(StatusCode::NOT_FOUND).body("Not Found"))
+        });
+        let shutdown = ShutdownHandle::new(listener.local_addr().unwrap());
         Server {
             threadpool: pool,
             listener,
-            handler: Arc::new(handler),
+            context: Arc::new(Context {
+                handler: middleware::compose(handler, self.middlewares),
+                panic_hook: self.panic_hook,
+                shutdown,
+                config: self.config,
+            }),
+            shutdown_timeout: self.shutdown_timeout,
+        }
+    }
+}
+
+impl Server {
+    /// Creates a new HTTP Server.
+    pub fn new(port: &str, threads: usize, handler: Handler) -> Server {
+        Server::builder()
+            .address(port)
+            .threads(threads)
+            .handler(handler)
+            .build()
+    }
+
+    /// Creates a builder for configuring an HTTP Server.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            address: "127.0.0.1:3000".to_string(),
+            threads: 4,
+            handler: None,
+            middlewares: vec![],
+            panic_hook: None,
+            shutdown_timeout: Duration::from_secs(30),
+            config: ServerConfig::default(),
         }
     }
 
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Returns a handle that can shut the server down from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.context.shutdown.clone()
+    }
+
     /// Starts the HTTP server.
-    /// It will run forever.
-    pub fn start(&self) {
+    /// It runs until a shutdown is requested through a [`ShutdownHandle`],
+    /// then waits for in-flight requests to finish before returning.
+    pub fn start(mut self) {
         println!(
             "Blitzkrieg Web Server is running on {}",
             self.listener.local_addr().unwrap()
         );
         for stream in self.listener.incoming() {
-            let mut stream = stream.unwrap();
-            let handler = Arc::clone(&self.handler);
-            self.threadpool.execute(move || loop {
-                let mut cloned_stream = stream.try_clone().unwrap();
-                let mut reader = BufReader::new(&mut cloned_stream);
-                loop {
-                    let request = Request::from_tcp_stream(&mut reader);
-                    // stream
-                    //     .set_read_timeout(Some(Duration::from_secs(5)))
-                    //     .unwrap();
-                    // stream
-                    //     .set_write_timeout(Some(Duration::from_secs(5)))
-                    //     .unwrap();
-                    match request {
-                        Ok(request) => {
-                            println!("{} {}", request.method, request.path);
-                            let response = handler(request);
-                            match stream.write_all(&response.into_bytes()) {
-                                Ok(_) => match stream.flush() {
-                                    Ok(_) => {}
-                                    Err(err) => {
-                                        println!("Error in flushing response. {}", err);
-                                    }
-                                },
-                                Err(err) => {
-                                    println!("Error in writing response. {}", err);
-                                }
-                            }
-                        }
-                        Err(error) => {
-                            if error == "Finished".to_string() {
-                                break;
-                            }
-                            println!("Error in request. {error}");
-                            if error == "Resource temporarily unavailable (os error 11)".to_string()
-                                || error == "Connection reset by peer (os error 104)".to_string()
-                            {
-                                break;
-                            }
-                            let error_response = Response::new(500).body(&error);
-                            match stream.write_all(&error_response.into_bytes()) {
-                                Ok(_) => {
-                                    break;
-                                }
-                                Err(err) => {
-                                    println!("Error in sending generic response. {}", err);
-                                    break;
-                                }
-                            }
-                        }
-                    }
+            if self.context.shutdown.is_shutdown() {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    println!("Error in accepting connection. {err}");
+                    continue;
+                }
+            };
+            let context = Arc::clone(&self.context);
+            self.threadpool
+                .execute(move || handle_connection(stream, context));
+        }
+        println!("Blitzkrieg Web Server is shutting down");
+        if !self.threadpool.shutdown(self.shutdown_timeout) {
+            self.context.shutdown.close_all();
+        }
+    }
+}
+
+/// A TCP stream whose reads fail once a deadline has passed,
+/// or once a single read has waited too long for data.
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+    idle_timeout: Option<Duration>,
+}
+
+impl DeadlineStream {
+    /// Sets a deadline `timeout` from now, or removes it.
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.idle_timeout = None;
+    }
+
+    /// Lets each read wait up to `timeout` for data, however long the reads take together.
+    fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.deadline = None;
+        self.idle_timeout = timeout;
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(ErrorKind::TimedOut.into());
+                }
+                Some(remaining)
+            }
+            None => self.idle_timeout,
+        };
+        self.stream.set_read_timeout(remaining)?;
+        self.stream.read(buf)
+    }
+}
+
+/// The reading half of a connection.
+type Connection = BufReader<DeadlineStream>;
+
+/// Where a streamed body puts the connection back once it's dropped.
+type BodySlot = Arc<Mutex<Option<BodyReader<Connection>>>>;
+
+/// A request body read straight from the connection by the handler.
+struct StreamedBody {
+    body: Option<BodyReader<Connection>>,
+    slot: BodySlot,
+}
+
+impl Read for StreamedBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.body {
+            Some(body) => body.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for StreamedBody {
+    fn drop(&mut self) {
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        *slot = self.body.take();
+    }
+}
+
+/// The connection while a request is handled.
+enum Lent {
+    /// The request was read whole, so the connection is ready for the next one.
+    Owned(Connection),
+    /// The handler got the connection to stream the body from.
+    Streamed(BodySlot),
+}
+
+impl Lent {
+    /// Takes the connection back, discarding whatever is left of a streamed body.
+    /// Returns `None` if the connection can't be used for another request.
+    ///
+    /// At most `max_drain` bytes are discarded: reading a larger rest would keep the
+    /// worker busy for a client whose upload was already rejected, so the connection
+    /// is closed instead.
+    fn reclaim(self, max_drain: u64) -> Option<Connection> {
+        match self {
+            Self::Owned(connection) => Some(connection),
+            Self::Streamed(slot) => {
+                // The body is missing if the handler kept it somewhere.
+                let body = slot.lock().unwrap_or_else(PoisonError::into_inner).take();
+                let mut body = body?;
+                if body
+                    .remaining()
+                    .is_some_and(|remaining| remaining > max_drain)
+                {
+                    return None;
+                }
+                let drained =
+                    io::copy(&mut (&mut body).take(max_drain + 1), &mut io::sink()).ok()?;
+                if drained > max_drain {
+                    return None;
+                }
+                Some(body.into_inner())
+            }
+        }
+    }
+}
+
+/// Reads a request, applying the header and body read timeouts.
+fn read_request(
+    mut connection: Connection,
+    config: &ServerConfig,
+) -> Result<(Request, Lent), Error> {
+    connection.get_mut().set_timeout(config.header_read_timeout);
+    let head = Request::read_head(&mut connection, &config.limits)?;
+    connection.get_mut().set_timeout(config.body_read_timeout);
+    let mut request = Request::parse_head(&head)?;
+    if config.stream_multipart {
+        if let Some(boundary) = request.multipart_boundary()? {
+            let limits = Limits {
+                max_body_size: usize::MAX,
+                ..config.limits.clone()
+            };
+            let codings = http::content_codings(&mut request.headers)?;
+            // A large upload can take any time, as long as the client keeps sending it.
+            connection
+                .get_mut()
+                .set_idle_timeout(config.body_read_timeout);
+            let slot = BodySlot::default();
+            let body = StreamedBody {
+                body: Some(BodyReader::new(connection, &head, &limits)?),
+                slot: Arc::clone(&slot),
+            };
+            // However well a body is compressed, it can't decode to more than the limit.
+            let body = http::decoder(body, &codings, config.limits.max_body_size);
+            request.body = Some(BodyType::Multipart(Multipart::new(body, &boundary)));
+            return Ok((request, Lent::Streamed(slot)));
+        }
+    }
+    let (body, trailers) = Request::read_body(&mut connection, &head, &config.limits)?;
+    let body = http::decode(&mut request.headers, body, config.limits.max_body_size)?;
+    request.body = Request::parse_body(&request.headers, body)?;
+    request.trailers = trailers;
+    Ok((request, Lent::Owned(connection)))
+}
+
+/// Serves requests from a connection until it's closed.
+fn handle_connection(mut stream: TcpStream, context: Arc<Context>) {
+    let shutdown = &context.shutdown;
+    let config = &context.config;
+    let Some(registration) = shutdown.register(&stream) else {
+        return;
+    };
+    if let Err(err) = stream.set_write_timeout(config.write_timeout) {
+        println!("Error in setting write timeout. {err}");
+    }
+    let mut connection = BufReader::new(DeadlineStream {
+        stream: stream.try_clone().unwrap(),
+        deadline: None,
+        idle_timeout: None,
+    });
+    loop {
+        // Wait for the next request while the connection is idle.
+        if !registration.set_idle(true) {
+            break;
+        }
+        connection.get_mut().set_timeout(config.keep_alive_timeout);
+        match connection.fill_buf() {
+            // The client closed the connection or stayed idle for too long.
+            Ok([]) | Err(_) => break,
+            Ok(_) => {}
+        }
+        registration.set_idle(false);
+        let (request, lent) = match read_request(connection, config) {
+            Ok(request) => request,
+            Err(error) => {
+                if error == Error::ConnectionClosed {
+                    break;
+                }
+                println!("Error in request. {error}");
+                // The rest of the stream can't be trusted after a bad request, so the connection is closed.
+                let Some(status_code) = error.status_code() else {
+                    break;
+                };
+                let mut error_response = Response::new(status_code).body(&error.to_string());
+                if error == Error::UnsupportedContentEncoding {
+                    error_response = error_response.header("Accept-Encoding", ACCEPTED_CODINGS);
+                }
+                if let Err(err) =
+                    error_response.write_for(&mut stream, Version::Http11, false, false)
+                {
+                    println!("Error in sending generic response. {}", err);
                 }
                 break;
-            });
+            }
+        };
+        println!("{} {}", request.method, request.path);
+        let (method, path) = (request.method.clone(), request.path.clone());
+        let version = request.version;
+        let head = request.method == HTTPMethod::HEAD;
+        let keep_alive = request.keep_alive();
+        // Other methods can change the resource, so only their handlers can evaluate
+        // the preconditions in time.
+        let preconditions = request.preconditions();
+        let response = call_handler(&context, request);
+        let response = match preconditions.is_get_or_head() {
+            true => preconditions.apply(response),
+            false => response,
+        };
+        // Let the current request finish, but don't wait for another one.
+        let keep_alive = keep_alive && !shutdown.is_shutdown();
+        let mut writer = BufWriter::new(&mut stream);
+        // Streamed bodies run user code while they're written, so they can panic too.
+        let written = panic::catch_unwind(AssertUnwindSafe(|| {
+            response
+                .write_for(&mut writer, version, keep_alive, head)
+                .and_then(|keep_alive| writer.flush().map(|_| keep_alive))
+        }));
+        match written {
+            Ok(Ok(true)) => {}
+            Ok(Ok(false)) => break,
+            Ok(Err(err)) => {
+                // The client can't tell where a partly written response ends.
+                println!("Error in writing response. {err}");
+                break;
+            }
+            Err(payload) => {
+                // Part of the response may be sent already, so the connection is closed.
+                let message = panic_message(payload);
+                println!("Response body panicked on {method} {path}. {message}");
+                break;
+            }
+        }
+        connection = match lent.reclaim(config.limits.max_body_size as u64) {
+            Some(connection) => connection,
+            None => break,
+        };
+    }
+}
+
+/// Calls the handler, turning a panic into a 500 response.
+fn call_handler(context: &Context, request: Request) -> Response {
+    let (method, path) = (request.method.clone(), request.path.clone());
+    match panic::catch_unwind(AssertUnwindSafe(|| (context.handler)(request))) {
+        Ok(response) => response,
+        Err(payload) => {
+            let message = panic_message(payload);
+            println!("Handler panicked on {method} {path}. {message}");
+            if let Some(hook) = &context.panic_hook {
+                let info = HandlerPanic {
+                    method,
+                    path,
+                    message,
+                };
+                // A panicking hook mustn't take the worker down either.
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(&info)));
+            }
+            Response::new(StatusCode::INTERNAL_SERVER_ERROR).body("Internal Server Error")
         }
     }
 }
+
+/// Returns the message a panic was started with.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Box<dyn Any>".to_string(),
+        },
+    }
+}
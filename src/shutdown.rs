@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// A handle for gracefully shutting down a running [`crate::server::Server`].
+///
+/// Shutting down stops the server from accepting new connections,
+/// closes idle keep-alive connections and lets busy connections finish their current request.
+/// [`crate::server::Server::start`] then waits for the workers and returns.
+///
+/// The handle is cheap to clone and can be moved to other threads.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    requested: AtomicBool,
+    address: SocketAddr,
+    next_id: AtomicUsize,
+    /// Open connections and whether they are idle, waiting for the next request.
+    connections: Mutex<HashMap<usize, (TcpStream, bool)>>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(address: SocketAddr) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                requested: AtomicBool::new(false),
+                address,
+                next_id: AtomicUsize::new(0),
+                connections: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Requests the server to shut down.
+    pub fn shutdown(&self) {
+        if self.inner.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for (stream, idle) in self.connections().values() {
+            if *idle {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+        // Wake up the accept loop, which is blocked waiting for a connection.
+        let mut address = self.inner.address;
+        if address.ip().is_unspecified() {
+            match address {
+                SocketAddr::V4(_) => address.set_ip([127, 0, 0, 1].into()),
+                SocketAddr::V6(_) => address.set_ip(std::net::Ipv6Addr::LOCALHOST.into()),
+            }
+        }
+        let _ = TcpStream::connect_timeout(&address, Duration::from_secs(1));
+    }
+
+    /// Returns whether a shutdown was requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Shuts the server down when the process receives SIGINT or SIGTERM.
+    #[cfg(target_os = "linux")]
+    pub fn shutdown_on_signals(&self) {
+        signals::install();
+        let handle = self.clone();
+        std::thread::spawn(move || {
+            while !handle.is_shutdown() {
+                if signals::received() {
+                    println!("Received shutdown signal");
+                    handle.shutdown();
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        });
+    }
+
+    /// Registers a connection so it can be closed on shutdown.
+    /// It stays registered until the returned guard is dropped, even by a panic.
+    pub(crate) fn register(&self, stream: &TcpStream) -> Option<Registration> {
+        let stream = stream.try_clone().ok()?;
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.connections().insert(id, (stream, false));
+        Some(Registration {
+            handle: self.clone(),
+            id,
+        })
+    }
+
+    /// Closes every open connection, busy or not.
+    pub(crate) fn close_all(&self) {
+        for (stream, _) in self.connections().values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn connections(&self) -> std::sync::MutexGuard<'_, HashMap<usize, (TcpStream, bool)>> {
+        self.inner
+            .connections
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A registered connection, removed from the open connections when dropped.
+/// The registry holds a clone of the stream, so the socket is only closed after that.
+pub(crate) struct Registration {
+    handle: ShutdownHandle,
+    id: usize,
+}
+
+impl Registration {
+    /// Marks the connection as idle or busy.
+    /// Returns false if the server is shutting down, in which case an idle connection should be closed.
+    pub(crate) fn set_idle(&self, idle: bool) -> bool {
+        let mut connections = self.handle.connections();
+        if let Some(connection) = connections.get_mut(&self.id) {
+            connection.1 = idle;
+        }
+        // Checked while holding the lock so a concurrent shutdown can't miss this connection.
+        !self.handle.is_shutdown()
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.handle.connections().remove(&self.id);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod signals {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    extern "C" fn on_signal(_: i32) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+        unsafe {
+            signal(SIGINT, on_signal);
+            signal(SIGTERM, on_signal);
+        }
+    }
+
+    pub fn received() -> bool {
+        RECEIVED.load(Ordering::SeqCst)
+    }
+}
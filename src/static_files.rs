@@ -0,0 +1,288 @@
+use crate::http::{path_segments, HTTPMethod, Request, Response, StatusCode};
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Serves the files of a directory under a URL prefix.
+///
+/// `StaticFiles::new("/static", "public")` serves `public/css/main.css` at
+/// `/static/css/main.css`. Files are streamed from disk with a `Content-Type`
+/// guessed from their extension, and support `Range` and conditional requests
+/// through their `ETag` and `Last-Modified`. Directories are served through
+/// their `index.html`, or as a listing if enabled. Paths can't escape the
+/// directory, neither with `..` nor through symbolic links, and hidden files
+/// like `.env` or `.git/` aren't served unless enabled.
+///
+/// It can be used as the server's handler, or for a wildcard route of a
+/// [`crate::router::Router`]:
+///
+/// ```no_run
+/// use blitzkrieg::router::Router;
+/// use blitzkrieg::static_files::StaticFiles;
+///
+/// let files = StaticFiles::new("/static", "public");
+/// let router = Router::new().get("/static/*path", move |req| files.handle(req));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    /// The URL prefix, without a trailing slash.
+    prefix: String,
+    root: PathBuf,
+    listing: bool,
+    dotfiles: bool,
+}
+
+impl StaticFiles {
+    /// Creates a handler serving `dir` under the URL `prefix`.
+    pub fn new(prefix: &str, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            root: dir.into(),
+            listing: false,
+            dotfiles: false,
+        }
+    }
+
+    /// Sets whether directories without an `index.html` are listed. Defaults to `false`.
+    pub fn listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    /// Sets whether files and directories whose name starts with a `.` are served
+    /// and listed. Defaults to `false`.
+    pub fn dotfiles(mut self, dotfiles: bool) -> Self {
+        self.dotfiles = dotfiles;
+        self
+    }
+
+    /// Serves a GET or HEAD request.
+    pub fn handle(&self, request: Request) -> Response {
+        if !matches!(request.method, HTTPMethod::GET | HTTPMethod::HEAD) {
+            return Response::new(StatusCode::METHOD_NOT_ALLOWED)
+                .header("Allow", "GET, HEAD")
+                .body("Method Not Allowed");
+        }
+        let Some(path) = self.resolve(&request.raw_path) else {
+            return not_found();
+        };
+        let Ok(metadata) = fs::metadata(&path) else {
+            return not_found();
+        };
+        if !metadata.is_dir() {
+            return serve_file(&request, &path);
+        }
+        // Relative links in the page only work if the URL ends with a slash.
+        if !request.path.ends_with('/') {
+            // A path starting with `//` would be taken for another host.
+            let mut location = format!("/{}/", encode_path(request.path.trim_start_matches('/')));
+            if let Some(query) = &request.raw_query {
+                location = format!("{location}?{}", encode_query(query));
+            }
+            return Response::new(StatusCode::MOVED_PERMANENTLY)
+                .header("Location", &location)
+                .body("Moved Permanently");
+        }
+        let index = path.join("index.html");
+        if index.is_file() {
+            return serve_file(&request, &index);
+        }
+        if self.listing {
+            return list_directory(&path, &request.path, self.dotfiles);
+        }
+        not_found()
+    }
+
+    /// Turns the StaticFiles into a handler that can be passed to [`crate::server::Server::new`].
+    pub fn into_handler(self) -> Box<dyn Fn(Request) -> Response + Send + Sync> {
+        Box::new(move |request| self.handle(request))
+    }
+
+    /// Maps a raw request path to a path inside the directory.
+    /// Returns `None` if the request path is outside the prefix or if it would
+    /// escape the directory.
+    fn resolve(&self, raw_path: &str) -> Option<PathBuf> {
+        let mut segments = path_segments(raw_path);
+        for prefix in self.prefix.split('/').filter(|s| !s.is_empty()) {
+            if segments.next()? != prefix {
+                return None;
+            }
+        }
+        let mut path = self.root.clone();
+        for segment in segments {
+            match segment.as_str() {
+                "." => {}
+                ".." => return None,
+                _ if !self.dotfiles && segment.starts_with('.') => return None,
+                // A decoded segment can contain characters that separate paths, like an
+                // encoded `/`, which isn't a separator in the URL.
+                _ if segment.contains(['/', '\\', '\0']) => return None,
+                _ => path.push(segment),
+            }
+        }
+        // Symbolic links are followed, but only to targets inside the directory.
+        let root = fs::canonicalize(&self.root).ok()?;
+        let path = fs::canonicalize(path).ok()?;
+        path.starts_with(root).then_some(path)
+    }
+}
+
+/// Streams a file with a `Content-Type` guessed from its extension,
+/// or the parts of it the request's `Range` asks for.
+///
+/// The `ETag` is made of the modification time and the size, so the server
+/// can answer conditional requests without reading the file.
+fn serve_file(request: &Request, path: &Path) -> Response {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return not_found(),
+    };
+    let metadata = match file.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return not_found(),
+    };
+    let length = metadata.len();
+    let mut response = Response::new(StatusCode::OK);
+    if let Ok(modified) = metadata.modified() {
+        let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let etag = format!(
+            "{:x}.{:x}-{length:x}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        );
+        response = response.etag(&etag).last_modified(modified);
+    }
+    response.ranged_reader(request, file, length, mime_type(path))
+}
+
+/// Renders an HTML page linking to the entries of a directory.
+fn list_directory(path: &Path, request_path: &str, dotfiles: bool) -> Response {
+    let Ok(entries) = fs::read_dir(path) else {
+        return not_found();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let mut name = entry.file_name().into_string().ok()?;
+            if !dotfiles && name.starts_with('.') {
+                return None;
+            }
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            Some(name)
+        })
+        .collect();
+    names.sort();
+    let title = escape_html(&format!("Index of {request_path}"));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    if request_path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for name in names {
+        let _ = writeln!(
+            html,
+            "<li><a href=\"{}\">{}</a></li>",
+            escape_html(&encode_path(&name)),
+            escape_html(&name)
+        );
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Response::new(StatusCode::OK)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(&html)
+}
+
+fn not_found() -> Response {
+    Response::new(StatusCode::NOT_FOUND).body("Not Found")
+}
+
+/// Returns the media type of a file, guessed from its extension.
+/// Unknown extensions are `application/octet-stream`.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Percent-encodes a decoded path so it can be used in a URL.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Percent-encodes the bytes of a raw query string that can't be sent in a header,
+/// like control characters. Existing escapes are kept.
+fn encode_query(query: &str) -> String {
+    let mut encoded = String::with_capacity(query.len());
+    for byte in query.bytes() {
+        if byte.is_ascii_graphic() && byte != b'#' {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Escapes the characters that have a meaning in HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
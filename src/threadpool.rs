@@ -1,10 +1,12 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex, PoisonError},
     thread,
+    time::{Duration, Instant},
 };
 pub struct ThreadPool {
     pub workers: Vec<Worker>,
-    pub sender: mpsc::Sender<Job>,
+    pub sender: Option<mpsc::Sender<Job>>,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
@@ -29,7 +31,10 @@ impl ThreadPool {
             workers.push(Worker::new(id, Arc::clone(&receiver)));
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
     }
 
     pub fn execute<F>(&self, f: F)
@@ -37,22 +42,81 @@ impl ThreadPool {
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
-        self.sender.send(job).unwrap();
+        self.sender.as_ref().unwrap().send(job).unwrap();
+    }
+
+    /// Stops accepting jobs and waits for the workers to finish the queued ones.
+    ///
+    /// Workers still running after the timeout are detached.
+    /// Returns whether every worker was joined.
+    pub fn shutdown(&mut self, timeout: Duration) -> bool {
+        drop(self.sender.take());
+        let deadline = Instant::now() + timeout;
+        loop {
+            let running = self
+                .workers
+                .iter()
+                .filter(|worker| worker.thread.as_ref().is_some_and(|t| !t.is_finished()))
+                .count();
+            if running == 0 || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut all_joined = true;
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                if thread.is_finished() {
+                    let _ = thread.join();
+                } else {
+                    println!("Worker {} didn't finish in time, detaching it", worker.id);
+                    all_joined = false;
+                }
+            }
+        }
+        all_joined
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
     }
 }
 
 pub struct Worker {
     pub id: usize,
-    pub thread: thread::JoinHandle<()>,
+    pub thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
     fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
         let thread = thread::spawn(move || loop {
-            let job = receiver.lock().unwrap().recv().unwrap();
-            job();
+            // The lock is only held while waiting for a job, so a poisoned lock is still usable.
+            let message = receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+            match message {
+                Ok(job) => {
+                    // A panicking job mustn't kill the worker, or the pool would shrink for good.
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        println!("Worker {id} recovered from a panicking job");
+                    }
+                }
+                // The sender was dropped, so the pool is shutting down.
+                Err(_) => break,
+            }
         });
 
-        Worker { id, thread }
+        Worker {
+            id,
+            thread: Some(thread),
+        }
     }
 }